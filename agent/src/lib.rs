@@ -1,8 +1,22 @@
 use chrono::Utc;
 use ipc_channel::ipc::IpcSender;
-use shared::{class::ClassIdentifier, descriptor::MethodDescriptor};
-use std::{ffi::CStr, os::raw::c_int, path::PathBuf, sync::OnceLock};
-use tracing::debug;
+use shared::{
+    class::ClassIdentifier,
+    descriptor::{BaseType, DescriptorError, FieldType, MethodDescriptor, ReturnDescriptor},
+    signature::{ClassSignature, MethodSignature},
+    value::{ObjectValue, Value},
+};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::CStr,
+    os::raw::c_int,
+    path::PathBuf,
+    sync::{
+        OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use tracing::{debug, error, warn};
 use tracing_subscriber::{
     EnvFilter,
     fmt::{self},
@@ -17,6 +31,22 @@ mod bindings {
 
 static SENDER: OnceLock<IpcSender<shared::AgentMessage>> = OnceLock::new();
 static CONFIG: OnceLock<shared::Config> = OnceLock::new();
+static MATCHER: OnceLock<shared::matcher::Matcher> = OnceLock::new();
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+const ACC_STATIC: i32 = 0x0008;
+const MAX_TO_STRING_LENGTH: usize = 100;
+/// Events after which the cached thread info is looked up again, so that renamed threads
+/// show up under their new name.
+const THREAD_INFO_REFRESH: u32 = 1000;
+
+thread_local! {
+    /// Set while the agent itself calls into Java, so that those calls are not traced.
+    static SUPPRESS_EVENTS: Cell<bool> = const { Cell::new(false) };
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+    /// Identity of the thread and the number of events it was used for since the lookup.
+    static THREAD_INFO: RefCell<Option<(shared::ThreadInfo, u32)>> = const { RefCell::new(None) };
+}
 
 #[unsafe(export_name = "Agent_OnLoad")]
 extern "C" fn agent_on_load(
@@ -36,8 +66,23 @@ extern "C" fn agent_on_load(
         let server_name = options.next().unwrap();
         let config_arg = options.next().unwrap();
         let config_path = PathBuf::from(config_arg);
-        let config = shared::load_config(config_path);
+        let config = match shared::load_config(config_path) {
+            Ok(config) => config,
+            Err(err) => {
+                error!("failed to load {}: {err}", config_arg);
+                return bindings::JNI_ERR;
+            }
+        };
+        // panicking here would abort the JVM without a readable message
+        let matcher = match shared::matcher::Matcher::new(&config) {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                error!("{err}");
+                return bindings::JNI_ERR;
+            }
+        };
         CONFIG.set(config).unwrap();
+        MATCHER.set(matcher).unwrap();
 
         let tx: IpcSender<shared::AgentMessage> =
             IpcSender::connect(server_name.to_string()).unwrap();
@@ -57,10 +102,17 @@ extern "C" fn agent_on_load(
             panic!("error getting env: {}", result);
         };
 
+        let trace_exceptions = MATCHER.get().unwrap().traces_exceptions();
+        let allocations = CONFIG.get().unwrap().allocations.clone();
+
         let callbacks = bindings::jvmtiEventCallbacks {
             ClassLoad: Some(class_load),
             MethodEntry: Some(method_entry),
             MethodExit: Some(method_exit),
+            Exception: Some(exception),
+            ExceptionCatch: Some(exception_catch),
+            SampledObjectAlloc: Some(sampled_object_alloc),
+            VMObjectAlloc: Some(vm_object_alloc),
             ..Default::default()
         };
 
@@ -75,10 +127,25 @@ extern "C" fn agent_on_load(
         let mut capabilities: bindings::jvmtiCapabilities = std::mem::zeroed();
         capabilities.set_can_generate_method_entry_events(1);
         capabilities.set_can_generate_method_exit_events(1);
+        capabilities.set_can_access_local_variables(1);
+        capabilities.set_can_get_line_numbers(1);
+        if trace_exceptions {
+            capabilities.set_can_generate_exception_events(1);
+        }
+        if allocations.is_some() {
+            capabilities.set_can_generate_sampled_object_alloc_events(1);
+            capabilities.set_can_generate_vm_object_alloc_events(1);
+        }
 
         let result = (*(*env)).AddCapabilities.unwrap()(env, &capabilities);
         assert_eq!(result, 0);
 
+        if let Some(allocations) = &allocations {
+            let result =
+                (*(*env)).SetHeapSamplingInterval.unwrap()(env, allocations.sampling_interval);
+            assert_eq!(result, 0);
+        }
+
         let result = (*(*env)).SetEventNotificationMode.unwrap()(
             env,
             bindings::jvmtiEventMode_JVMTI_ENABLE,
@@ -114,6 +181,46 @@ extern "C" fn agent_on_load(
         );
 
         assert_eq!(result, 0);
+
+        if trace_exceptions {
+            let result = (*(*env)).SetEventNotificationMode.unwrap()(
+                env,
+                bindings::jvmtiEventMode_JVMTI_ENABLE,
+                bindings::jvmtiEvent_JVMTI_EVENT_EXCEPTION,
+                std::ptr::null_mut(),
+            );
+
+            assert_eq!(result, 0);
+
+            let result = (*(*env)).SetEventNotificationMode.unwrap()(
+                env,
+                bindings::jvmtiEventMode_JVMTI_ENABLE,
+                bindings::jvmtiEvent_JVMTI_EVENT_EXCEPTION_CATCH,
+                std::ptr::null_mut(),
+            );
+
+            assert_eq!(result, 0);
+        }
+
+        if allocations.is_some() {
+            let result = (*(*env)).SetEventNotificationMode.unwrap()(
+                env,
+                bindings::jvmtiEventMode_JVMTI_ENABLE,
+                bindings::jvmtiEvent_JVMTI_EVENT_SAMPLED_OBJECT_ALLOC,
+                std::ptr::null_mut(),
+            );
+
+            assert_eq!(result, 0);
+
+            let result = (*(*env)).SetEventNotificationMode.unwrap()(
+                env,
+                bindings::jvmtiEventMode_JVMTI_ENABLE,
+                bindings::jvmtiEvent_JVMTI_EVENT_VM_OBJECT_ALLOC,
+                std::ptr::null_mut(),
+            );
+
+            assert_eq!(result, 0);
+        }
     }
 
     debug!("agent loaded");
@@ -124,27 +231,41 @@ extern "C" fn agent_on_load(
 #[unsafe(no_mangle)]
 extern "C" fn class_load(
     jvmti_env: *mut bindings::jvmtiEnv,
-    _env: *mut bindings::JNIEnv,
-    _jthread: bindings::jthread,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
     class: bindings::jclass,
 ) {
     unsafe {
-        let name = get_class(jvmti_env, class);
+        let Some(name) = get_class(jvmti_env, class) else {
+            return;
+        };
         let timestamp = Utc::now().timestamp_micros();
 
-        if !CONFIG.get().unwrap().class_loads.contains(&name) {
+        if !MATCHER.get().unwrap().includes_class_load(&name) {
             return;
         }
 
-        let class_identifier = ClassIdentifier::parse(&name);
+        let class_identifier = match ClassIdentifier::parse(&name) {
+            Ok(class_identifier) => class_identifier,
+            Err(err) => {
+                warn!("skipping class load of {name}: {err}");
+                return;
+            }
+        };
+        let signature = get_class_generic_signature(jvmti_env, class);
+        let thread = get_thread_info(jvmti_env, env, jthread);
 
         SENDER
             .get()
             .unwrap()
-            .send(shared::AgentMessage::ClassLoad(shared::ClassLoadEvent {
-                timestamp,
-                class_identifier,
-            }))
+            .send(shared::AgentMessage::ClassLoad(Box::new(
+                shared::ClassLoadEvent {
+                    timestamp,
+                    class_identifier,
+                    signature,
+                    thread,
+                },
+            )))
             .unwrap();
     }
 }
@@ -152,122 +273,890 @@ extern "C" fn class_load(
 #[unsafe(no_mangle)]
 extern "C" fn method_entry(
     jvmti_env: *mut bindings::jvmtiEnv,
-    _env: *mut bindings::JNIEnv,
-    _jthread: bindings::jthread,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
     jmethod_id: bindings::jmethodID,
 ) {
-    let mut name: *mut i8 = std::ptr::null_mut();
-    let mut signature: *mut i8 = std::ptr::null_mut();
+    if SUPPRESS_EVENTS.get() {
+        return;
+    }
 
     unsafe {
-        (*(*jvmti_env)).GetMethodName.unwrap()(
-            jvmti_env,
-            jmethod_id,
-            &mut name,
-            &mut signature,
-            &mut std::ptr::null_mut(),
-        );
-        let name = CStr::from_ptr(name).to_string_lossy().to_string();
-        let signature = CStr::from_ptr(signature).to_string_lossy().to_string();
-        let descriptor = MethodDescriptor::new(&signature);
+        let Some((name, descriptor, signature, class_identifier)) =
+            get_traced_method(jvmti_env, env, jmethod_id)
+        else {
+            return;
+        };
 
-        let mut class: bindings::jclass = std::ptr::null_mut();
+        let arguments = get_arguments(jvmti_env, env, jthread, jmethod_id, &descriptor);
+        let thread = get_thread_info(jvmti_env, env, jthread);
 
-        (*(*jvmti_env)).GetMethodDeclaringClass.unwrap()(jvmti_env, jmethod_id, &mut class);
+        let timestamp = Utc::now().timestamp_micros();
+        SENDER
+            .get()
+            .unwrap()
+            .send(shared::AgentMessage::MethodEvent(Box::new(
+                shared::MethodEvent {
+                    timestamp,
+                    name,
+                    class_identifier,
+                    descriptor,
+                    signature,
+                    method_event_type: shared::MethodEventType::Entry,
+                    thread,
+                    arguments,
+                    return_value: None,
+                    was_popped_by_exception: false,
+                },
+            )))
+            .unwrap();
+    }
+}
 
-        let class_name = get_class(jvmti_env, class);
+#[unsafe(no_mangle)]
+extern "C" fn method_exit(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
+    jmethod_id: bindings::jmethodID,
+    was_popped_by_exception: bindings::jboolean,
+    return_value: bindings::jvalue,
+) {
+    if SUPPRESS_EVENTS.get() {
+        return;
+    }
 
-        if !CONFIG.get().unwrap().includes_method(&name, &class_name) {
+    unsafe {
+        let Some((name, descriptor, signature, class_identifier)) =
+            get_traced_method(jvmti_env, env, jmethod_id)
+        else {
             return;
-        }
+        };
 
-        let class_identifier = ClassIdentifier::parse(&class_name);
+        let thread = get_thread_info(jvmti_env, env, jthread);
+        let was_popped_by_exception = was_popped_by_exception != 0;
+
+        // the return value is undefined when the frame was popped by an exception
+        let return_value = if was_popped_by_exception {
+            None
+        } else {
+            get_return_value(jvmti_env, env, &descriptor.return_descriptor, return_value)
+        };
 
         let timestamp = Utc::now().timestamp_micros();
         SENDER
             .get()
             .unwrap()
-            .send(shared::AgentMessage::MethodEvent(shared::MethodEvent {
-                timestamp,
-                name,
-                class_identifier,
-                descriptor,
-                method_event_type: shared::MethodEventType::Entry,
-            }))
+            .send(shared::AgentMessage::MethodEvent(Box::new(
+                shared::MethodEvent {
+                    timestamp,
+                    name,
+                    class_identifier,
+                    descriptor,
+                    signature,
+                    method_event_type: shared::MethodEventType::Exit,
+                    thread,
+                    arguments: Vec::new(),
+                    return_value,
+                    was_popped_by_exception,
+                },
+            )))
             .unwrap();
     }
 }
 
 #[unsafe(no_mangle)]
-extern "C" fn method_exit(
+extern "C" fn exception(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
+    jmethod_id: bindings::jmethodID,
+    location: bindings::jlocation,
+    exception: bindings::jobject,
+    catch_method: bindings::jmethodID,
+    catch_location: bindings::jlocation,
+) {
+    if SUPPRESS_EVENTS.get() {
+        return;
+    }
+
+    unsafe {
+        let timestamp = Utc::now().timestamp_micros();
+
+        let Some((class_identifier, message)) = get_exception(jvmti_env, env, exception) else {
+            return;
+        };
+
+        let throw_location = get_method_location(jvmti_env, env, jmethod_id, location);
+        let catch_location = if catch_method.is_null() {
+            None
+        } else {
+            get_method_location(jvmti_env, env, catch_method, catch_location)
+        };
+        let thread = get_thread_info(jvmti_env, env, jthread);
+
+        SENDER
+            .get()
+            .unwrap()
+            .send(shared::AgentMessage::Exception(Box::new(
+                shared::ExceptionEvent {
+                    timestamp,
+                    exception_event_type: shared::ExceptionEventType::Thrown,
+                    class_identifier,
+                    message,
+                    throw_location,
+                    catch_location,
+                    thread,
+                },
+            )))
+            .unwrap();
+    }
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn exception_catch(
     jvmti_env: *mut bindings::jvmtiEnv,
-    _env: *mut bindings::JNIEnv,
-    _jthread: bindings::jthread,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
     jmethod_id: bindings::jmethodID,
-    _was_popped_by_exception: bindings::jboolean,
-    _return_value: bindings::jvalue,
+    location: bindings::jlocation,
+    exception: bindings::jobject,
+) {
+    if SUPPRESS_EVENTS.get() {
+        return;
+    }
+
+    unsafe {
+        let timestamp = Utc::now().timestamp_micros();
+
+        let Some((class_identifier, message)) = get_exception(jvmti_env, env, exception) else {
+            return;
+        };
+
+        let catch_location = get_method_location(jvmti_env, env, jmethod_id, location);
+        let thread = get_thread_info(jvmti_env, env, jthread);
+
+        SENDER
+            .get()
+            .unwrap()
+            .send(shared::AgentMessage::Exception(Box::new(
+                shared::ExceptionEvent {
+                    timestamp,
+                    exception_event_type: shared::ExceptionEventType::Caught,
+                    class_identifier,
+                    message,
+                    throw_location: None,
+                    catch_location,
+                    thread,
+                },
+            )))
+            .unwrap();
+    }
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn sampled_object_alloc(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
+    _object: bindings::jobject,
+    object_class: bindings::jclass,
+    size: bindings::jlong,
 ) {
+    unsafe {
+        send_allocation(
+            jvmti_env,
+            env,
+            jthread,
+            object_class,
+            size,
+            shared::AllocationEventType::Sampled,
+        );
+    }
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn vm_object_alloc(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
+    _object: bindings::jobject,
+    object_class: bindings::jclass,
+    size: bindings::jlong,
+) {
+    unsafe {
+        send_allocation(
+            jvmti_env,
+            env,
+            jthread,
+            object_class,
+            size,
+            shared::AllocationEventType::VirtualMachine,
+        );
+    }
+}
+
+unsafe fn send_allocation(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
+    object_class: bindings::jclass,
+    size: bindings::jlong,
+    allocation_event_type: shared::AllocationEventType,
+) {
+    // toString() calls of the agent allocate as well
+    if SUPPRESS_EVENTS.get() {
+        return;
+    }
+
+    unsafe {
+        let timestamp = Utc::now().timestamp_micros();
+
+        // the signature, since array classes are only parsed in that form
+        let Some(signature) = get_class_signature(jvmti_env, object_class) else {
+            return;
+        };
+        let class_identifier = match ClassIdentifier::parse(&signature) {
+            Ok(class_identifier) => class_identifier,
+            Err(err) => {
+                warn!("skipping allocation of {signature}: {err}");
+                return;
+            }
+        };
+
+        let max_frames = CONFIG
+            .get()
+            .unwrap()
+            .allocations
+            .as_ref()
+            .map_or(0, |allocations| allocations.max_frames);
+        let stack_trace = get_stack_trace(jvmti_env, env, jthread, max_frames);
+        let thread = get_thread_info(jvmti_env, env, jthread);
+
+        SENDER
+            .get()
+            .unwrap()
+            .send(shared::AgentMessage::Allocation(Box::new(
+                shared::AllocationEvent {
+                    timestamp,
+                    allocation_event_type,
+                    class_identifier,
+                    size,
+                    thread,
+                    stack_trace,
+                },
+            )))
+            .unwrap();
+    }
+}
+
+/// Returns at most `max_frames` frames of `jthread`, innermost first.
+unsafe fn get_stack_trace(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
+    max_frames: usize,
+) -> Vec<shared::MethodLocation> {
+    let mut frames: Vec<bindings::jvmtiFrameInfo> = Vec::with_capacity(max_frames);
+    let mut count = 0;
+
+    unsafe {
+        let result = (*(*jvmti_env)).GetStackTrace.unwrap()(
+            jvmti_env,
+            jthread,
+            0,
+            max_frames as i32,
+            frames.as_mut_ptr(),
+            &mut count,
+        );
+        if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+            return Vec::new();
+        }
+        frames.set_len(count as usize);
+
+        frames
+            .iter()
+            .filter_map(|frame| get_method_location(jvmti_env, env, frame.method, frame.location))
+            .collect()
+    }
+}
+
+/// A method as JVMTI names it, nothing is parsed yet.
+struct RawMethod {
+    name: String,
+    descriptor: String,
+    /// Binary name of the declaring class as [`ClassIdentifier`] displays it.
+    class: String,
+}
+
+impl RawMethod {
+    fn parse_descriptor(&self) -> Option<MethodDescriptor> {
+        MethodDescriptor::new(&self.descriptor)
+            .inspect_err(|err| warn!("skipping method {}{}: {err}", self.name, self.descriptor))
+            .ok()
+    }
+
+    fn parse_class(&self) -> Option<ClassIdentifier> {
+        ClassIdentifier::parse(&self.class)
+            .inspect_err(|err| warn!("skipping method {}.{}: {err}", self.class, self.name))
+            .ok()
+    }
+}
+
+unsafe fn get_raw_method(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jmethod_id: bindings::jmethodID,
+) -> Option<RawMethod> {
     let mut name: *mut i8 = std::ptr::null_mut();
     let mut signature: *mut i8 = std::ptr::null_mut();
 
     unsafe {
-        (*(*jvmti_env)).GetMethodName.unwrap()(
+        let result = (*(*jvmti_env)).GetMethodName.unwrap()(
             jvmti_env,
             jmethod_id,
             &mut name,
             &mut signature,
-            &mut std::ptr::null_mut(),
+            std::ptr::null_mut(),
         );
+        if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+            return None;
+        }
 
-        let name = CStr::from_ptr(name).to_string_lossy().to_string();
-        let signature = CStr::from_ptr(signature).to_string_lossy().to_string();
-        let descriptor = MethodDescriptor::new(&signature);
+        let name_string = CStr::from_ptr(name).to_string_lossy().to_string();
+        let signature_string = CStr::from_ptr(signature).to_string_lossy().to_string();
+        (*(*jvmti_env)).Deallocate.unwrap()(jvmti_env, name as *mut u8);
+        (*(*jvmti_env)).Deallocate.unwrap()(jvmti_env, signature as *mut u8);
 
         let mut class: bindings::jclass = std::ptr::null_mut();
+        let result =
+            (*(*jvmti_env)).GetMethodDeclaringClass.unwrap()(jvmti_env, jmethod_id, &mut class);
+        if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+            return None;
+        }
 
-        (*(*jvmti_env)).GetMethodDeclaringClass.unwrap()(jvmti_env, jmethod_id, &mut class);
+        let class_name = get_class(jvmti_env, class);
+        (*(*env)).DeleteLocalRef.unwrap()(env, class);
+        let class_name = class_name?;
+
+        Some(RawMethod {
+            name: name_string,
+            descriptor: signature_string,
+            class: shared::class::display_name(&class_name).into_owned(),
+        })
+    }
+}
+
+/// Returns the name, descriptor, generic signature and declaring class of a method the config
+/// traces, `None` for all others or if its descriptor or class name can't be parsed.
+///
+/// Runs for every method entry and exit in the JVM, so the cheap checks on the raw names come
+/// first and only traced methods are parsed.
+unsafe fn get_traced_method(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jmethod_id: bindings::jmethodID,
+) -> Option<(
+    String,
+    MethodDescriptor,
+    Option<MethodSignature>,
+    ClassIdentifier,
+)> {
+    let matcher = MATCHER.get().unwrap();
+
+    unsafe {
+        let raw_method = get_raw_method(jvmti_env, env, jmethod_id)?;
+        if !matcher.may_include_method(&raw_method.name, &raw_method.class) {
+            return None;
+        }
 
+        let descriptor = raw_method.parse_descriptor()?;
+        if !matcher.includes_method(&raw_method.name, &raw_method.class, &descriptor) {
+            return None;
+        }
+
+        let class_identifier = raw_method.parse_class()?;
+        let signature = get_method_generic_signature(jvmti_env, jmethod_id);
+
+        Some((raw_method.name, descriptor, signature, class_identifier))
+    }
+}
+
+unsafe fn get_method_generic_signature(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    jmethod_id: bindings::jmethodID,
+) -> Option<MethodSignature> {
+    let mut generic: *mut i8 = std::ptr::null_mut();
+
+    unsafe {
+        (*(*jvmti_env)).GetMethodName.unwrap()(
+            jvmti_env,
+            jmethod_id,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut generic,
+        );
+
+        take_generic_signature(jvmti_env, generic, MethodSignature::new)
+    }
+}
+
+unsafe fn get_method_location(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jmethod_id: bindings::jmethodID,
+    location: bindings::jlocation,
+) -> Option<shared::MethodLocation> {
+    unsafe {
+        let raw_method = get_raw_method(jvmti_env, env, jmethod_id)?;
+        let descriptor = raw_method.parse_descriptor()?;
+        let class_identifier = raw_method.parse_class()?;
+
+        Some(shared::MethodLocation {
+            class_identifier,
+            name: raw_method.name,
+            descriptor,
+            location,
+            line_number: get_line_number(jvmti_env, jmethod_id, location),
+        })
+    }
+}
+
+unsafe fn get_line_number(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    jmethod_id: bindings::jmethodID,
+    location: bindings::jlocation,
+) -> Option<i32> {
+    let mut entry_count = 0;
+    let mut table: *mut bindings::jvmtiLineNumberEntry = std::ptr::null_mut();
+
+    unsafe {
+        // fails for native methods and classes compiled without line numbers
+        let result = (*(*jvmti_env)).GetLineNumberTable.unwrap()(
+            jvmti_env,
+            jmethod_id,
+            &mut entry_count,
+            &mut table,
+        );
+        if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+            return None;
+        }
+
+        let line_number = std::slice::from_raw_parts(table, entry_count as usize)
+            .iter()
+            .filter(|entry| entry.start_location <= location)
+            .max_by_key(|entry| entry.start_location)
+            .map(|entry| entry.line_number);
+        (*(*jvmti_env)).Deallocate.unwrap()(jvmti_env, table as *mut u8);
+
+        line_number
+    }
+}
+
+/// Returns the class and detail message of `exception` if its class is traced.
+unsafe fn get_exception(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    exception: bindings::jobject,
+) -> Option<(ClassIdentifier, Option<String>)> {
+    unsafe {
+        let class = (*(*env)).GetObjectClass.unwrap()(env, exception);
         let class_name = get_class(jvmti_env, class);
+        (*(*env)).DeleteLocalRef.unwrap()(env, class);
+        let class_name = class_name?;
 
-        if !CONFIG.get().unwrap().includes_method(&name, &class_name) {
-            return;
+        if !MATCHER.get().unwrap().includes_exception(&class_name) {
+            return None;
         }
 
-        let class_identifier = ClassIdentifier::parse(&class_name);
+        // read the field instead of calling getMessage() to not run any Java code
+        let throwable = (*(*env)).FindClass.unwrap()(env, c"java/lang/Throwable".as_ptr());
+        let field = (*(*env)).GetFieldID.unwrap()(
+            env,
+            throwable,
+            c"detailMessage".as_ptr(),
+            c"Ljava/lang/String;".as_ptr(),
+        );
+        (*(*env)).DeleteLocalRef.unwrap()(env, throwable);
 
-        let timestamp = Utc::now().timestamp_micros();
-        SENDER
-            .get()
-            .unwrap()
-            .send(shared::AgentMessage::MethodEvent(shared::MethodEvent {
-                timestamp,
-                name,
-                class_identifier,
-                descriptor,
-                method_event_type: shared::MethodEventType::Exit,
-            }))
-            .unwrap();
+        let message = if field.is_null() {
+            (*(*env)).ExceptionClear.unwrap()(env);
+            None
+        } else {
+            let message = (*(*env)).GetObjectField.unwrap()(env, exception, field);
+            let to_string = get_string(env, message);
+            if !message.is_null() {
+                (*(*env)).DeleteLocalRef.unwrap()(env, message);
+            }
+
+            to_string
+        };
+
+        match ClassIdentifier::parse(&class_name) {
+            Ok(class_identifier) => Some((class_identifier, message)),
+            Err(err) => {
+                warn!("skipping exception {class_name}: {err}");
+                None
+            }
+        }
+    }
+}
+
+unsafe fn get_class(jvmti_env: *mut bindings::jvmtiEnv, class: bindings::jclass) -> Option<String> {
+    unsafe {
+        let signature = get_class_signature(jvmti_env, class)?;
+
+        // array classes have no `L...;` wrapper and are kept as they are
+        Some(
+            signature
+                .strip_prefix("L")
+                .and_then(|signature| signature.strip_suffix(";"))
+                .unwrap_or(&signature)
+                .replace("/", "."),
+        )
+    }
+}
+
+unsafe fn get_class_generic_signature(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    class: bindings::jclass,
+) -> Option<ClassSignature> {
+    let mut signature: *mut i8 = std::ptr::null_mut();
+    let mut generic: *mut i8 = std::ptr::null_mut();
+
+    unsafe {
+        (*(*jvmti_env)).GetClassSignature.unwrap()(jvmti_env, class, &mut signature, &mut generic);
+        (*(*jvmti_env)).Deallocate.unwrap()(jvmti_env, signature as *mut u8);
+
+        take_generic_signature(jvmti_env, generic, ClassSignature::new)
     }
 }
 
-unsafe fn get_class(jvmti_env: *mut bindings::jvmtiEnv, class: bindings::jclass) -> String {
+/// Parses and deallocates a generic signature returned by JVMTI, which is null for classes and
+/// methods that don't use generic types. Malformed signatures are dropped, the erased
+/// descriptor is still available.
+unsafe fn take_generic_signature<T>(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    generic: *mut i8,
+    parse: impl Fn(&str) -> Result<T, DescriptorError>,
+) -> Option<T> {
+    if generic.is_null() {
+        return None;
+    }
+
+    unsafe {
+        let raw = CStr::from_ptr(generic).to_string_lossy().to_string();
+        (*(*jvmti_env)).Deallocate.unwrap()(jvmti_env, generic as *mut u8);
+
+        parse(&raw)
+            .inspect_err(|err| warn!("ignoring generic signature {raw}: {err}"))
+            .ok()
+    }
+}
+
+unsafe fn get_class_signature(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    class: bindings::jclass,
+) -> Option<String> {
     let mut signature: *mut i8 = std::ptr::null_mut();
 
     unsafe {
-        (*(*jvmti_env)).GetClassSignature.unwrap()(
+        let result = (*(*jvmti_env)).GetClassSignature.unwrap()(
             jvmti_env,
             class,
             &mut signature,
-            &mut std::ptr::null_mut(),
+            std::ptr::null_mut(),
         );
+        if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+            return None;
+        }
 
-        let signature = CStr::from_ptr(signature).to_string_lossy().to_string();
-        signature
-            .strip_prefix("L")
-            .unwrap()
-            .strip_suffix(";")
-            .unwrap()
-            .replace("/", ".")
+        let signature_string = CStr::from_ptr(signature).to_string_lossy().to_string();
+        (*(*jvmti_env)).Deallocate.unwrap()(jvmti_env, signature as *mut u8);
+
+        Some(signature_string)
+    }
+}
+
+/// Returns the identity of the current thread, which is cached and looked up again every
+/// [`THREAD_INFO_REFRESH`] events.
+unsafe fn get_thread_info(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
+) -> shared::ThreadInfo {
+    let cached = THREAD_INFO.with_borrow_mut(|cached| match cached {
+        Some((thread_info, uses)) if *uses < THREAD_INFO_REFRESH => {
+            *uses += 1;
+            Some(thread_info.clone())
+        }
+        _ => None,
+    });
+    if let Some(thread_info) = cached {
+        return thread_info;
+    }
+
+    let id = THREAD_ID.with(|id| *id);
+
+    unsafe {
+        let mut info: bindings::jvmtiThreadInfo = std::mem::zeroed();
+        let result = (*(*jvmti_env)).GetThreadInfo.unwrap()(jvmti_env, jthread, &mut info);
+
+        // not cached, the lookup can succeed once the VM is further along
+        if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+            return shared::ThreadInfo {
+                id,
+                name: "unknown".to_string(),
+            };
+        }
+
+        let name = CStr::from_ptr(info.name).to_string_lossy().to_string();
+        (*(*jvmti_env)).Deallocate.unwrap()(jvmti_env, info.name as *mut u8);
+
+        if !info.thread_group.is_null() {
+            (*(*env)).DeleteLocalRef.unwrap()(env, info.thread_group);
+        }
+
+        if !info.context_class_loader.is_null() {
+            (*(*env)).DeleteLocalRef.unwrap()(env, info.context_class_loader);
+        }
+
+        let thread_info = shared::ThreadInfo { id, name };
+        THREAD_INFO.set(Some((thread_info.clone(), 0)));
+        thread_info
+    }
+}
+
+/// Reads the parameters of the method in the topmost frame of `jthread`.
+unsafe fn get_arguments(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
+    jmethod_id: bindings::jmethodID,
+    descriptor: &MethodDescriptor,
+) -> Vec<Value> {
+    let mut modifiers = 0;
+
+    unsafe {
+        (*(*jvmti_env)).GetMethodModifiers.unwrap()(jvmti_env, jmethod_id, &mut modifiers);
+
+        // slot 0 holds `this` for instance methods
+        let mut slot = if modifiers & ACC_STATIC == 0 { 1 } else { 0 };
+        let mut arguments = Vec::new();
+        for parameter in &descriptor.parameters {
+            arguments.push(get_local(jvmti_env, env, jthread, slot, parameter));
+
+            slot += match parameter {
+                FieldType::Base(BaseType::Long) | FieldType::Base(BaseType::Double) => 2,
+                _ => 1,
+            };
+        }
+
+        arguments
+    }
+}
+
+unsafe fn get_local(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    jthread: bindings::jthread,
+    slot: i32,
+    field_type: &FieldType,
+) -> Value {
+    unsafe {
+        match field_type {
+            FieldType::Base(BaseType::Long) => {
+                let mut value = 0;
+                let result =
+                    (*(*jvmti_env)).GetLocalLong.unwrap()(jvmti_env, jthread, 0, slot, &mut value);
+                if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+                    return Value::Unavailable;
+                }
+
+                Value::Long(value)
+            }
+            FieldType::Base(BaseType::Float) => {
+                let mut value = 0.0;
+                let result =
+                    (*(*jvmti_env)).GetLocalFloat.unwrap()(jvmti_env, jthread, 0, slot, &mut value);
+                if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+                    return Value::Unavailable;
+                }
+
+                Value::Float(value)
+            }
+            FieldType::Base(BaseType::Double) => {
+                let mut value = 0.0;
+                let result = (*(*jvmti_env)).GetLocalDouble.unwrap()(
+                    jvmti_env, jthread, 0, slot, &mut value,
+                );
+                if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+                    return Value::Unavailable;
+                }
+
+                Value::Double(value)
+            }
+            FieldType::Base(base_type) => {
+                let mut value = 0;
+                let result =
+                    (*(*jvmti_env)).GetLocalInt.unwrap()(jvmti_env, jthread, 0, slot, &mut value);
+                if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+                    return Value::Unavailable;
+                }
+
+                // sub-int types are widened to int in local variable slots
+                match base_type {
+                    BaseType::Byte => Value::Byte(value as i8),
+                    BaseType::Char => Value::Char(value as u16),
+                    BaseType::Short => Value::Short(value as i16),
+                    BaseType::Boolean => Value::Boolean(value != 0),
+                    _ => Value::Int(value),
+                }
+            }
+            FieldType::Object { .. } | FieldType::Array { .. } => {
+                let mut object: bindings::jobject = std::ptr::null_mut();
+                let result = (*(*jvmti_env)).GetLocalObject.unwrap()(
+                    jvmti_env,
+                    jthread,
+                    0,
+                    slot,
+                    &mut object,
+                );
+                if result != bindings::jvmtiError_JVMTI_ERROR_NONE {
+                    return Value::Unavailable;
+                }
+
+                let value = get_object_value(jvmti_env, env, object);
+                if !object.is_null() {
+                    (*(*env)).DeleteLocalRef.unwrap()(env, object);
+                }
+
+                value
+            }
+        }
+    }
+}
+
+unsafe fn get_return_value(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    return_descriptor: &ReturnDescriptor,
+    return_value: bindings::jvalue,
+) -> Option<Value> {
+    let field_type = match return_descriptor {
+        ReturnDescriptor::Void => return None,
+        ReturnDescriptor::FieldType(field_type) => field_type,
+    };
+
+    unsafe {
+        let value = match field_type {
+            FieldType::Base(BaseType::Byte) => Value::Byte(return_value.b),
+            FieldType::Base(BaseType::Char) => Value::Char(return_value.c),
+            FieldType::Base(BaseType::Double) => Value::Double(return_value.d),
+            FieldType::Base(BaseType::Float) => Value::Float(return_value.f),
+            FieldType::Base(BaseType::Int) => Value::Int(return_value.i),
+            FieldType::Base(BaseType::Long) => Value::Long(return_value.j),
+            FieldType::Base(BaseType::Short) => Value::Short(return_value.s),
+            FieldType::Base(BaseType::Boolean) => Value::Boolean(return_value.z != 0),
+            FieldType::Object { .. } | FieldType::Array { .. } => {
+                get_object_value(jvmti_env, env, return_value.l)
+            }
+        };
+
+        Some(value)
+    }
+}
+
+unsafe fn get_object_value(
+    jvmti_env: *mut bindings::jvmtiEnv,
+    env: *mut bindings::JNIEnv,
+    object: bindings::jobject,
+) -> Value {
+    if object.is_null() {
+        return Value::Null;
+    }
+
+    unsafe {
+        let class = (*(*env)).GetObjectClass.unwrap()(env, object);
+        let signature = get_class_signature(jvmti_env, class);
+        (*(*env)).DeleteLocalRef.unwrap()(env, class);
+
+        let Some(class_identifier) =
+            signature.and_then(|signature| ClassIdentifier::parse(&signature).ok())
+        else {
+            return Value::Unavailable;
+        };
+
+        let mut identity_hash = 0;
+        (*(*jvmti_env)).GetObjectHashCode.unwrap()(jvmti_env, object, &mut identity_hash);
+
+        let to_string = if class_identifier.to_string() == "java.lang.String" {
+            get_string(env, object)
+        } else if CONFIG.get().unwrap().call_to_string {
+            call_to_string(env, object)
+        } else {
+            None
+        };
+
+        Value::Object(ObjectValue {
+            class_identifier,
+            identity_hash,
+            to_string,
+        })
+    }
+}
+
+unsafe fn call_to_string(env: *mut bindings::JNIEnv, object: bindings::jobject) -> Option<String> {
+    unsafe {
+        let class = (*(*env)).GetObjectClass.unwrap()(env, object);
+        let method = (*(*env)).GetMethodID.unwrap()(
+            env,
+            class,
+            c"toString".as_ptr(),
+            c"()Ljava/lang/String;".as_ptr(),
+        );
+        (*(*env)).DeleteLocalRef.unwrap()(env, class);
+
+        SUPPRESS_EVENTS.set(true);
+        let string = (*(*env)).CallObjectMethod.unwrap()(env, object, method);
+        SUPPRESS_EVENTS.set(false);
+
+        if (*(*env)).ExceptionCheck.unwrap()(env) != 0 {
+            (*(*env)).ExceptionClear.unwrap()(env);
+            return None;
+        }
+
+        let to_string = get_string(env, string);
+        if !string.is_null() {
+            (*(*env)).DeleteLocalRef.unwrap()(env, string);
+        }
+
+        to_string
+    }
+}
+
+/// Copies at most [`MAX_TO_STRING_LENGTH`] characters of a `java.lang.String`.
+unsafe fn get_string(env: *mut bindings::JNIEnv, string: bindings::jstring) -> Option<String> {
+    if string.is_null() {
+        return None;
+    }
+
+    unsafe {
+        let length = (*(*env)).GetStringLength.unwrap()(env, string);
+        let bounded_length = length.min(MAX_TO_STRING_LENGTH as i32);
+
+        // modified UTF-8 needs at most 3 bytes per UTF-16 unit, plus the terminator
+        let mut buffer = vec![0 as std::os::raw::c_char; bounded_length as usize * 3 + 1];
+        (*(*env)).GetStringUTFRegion.unwrap()(env, string, 0, bounded_length, buffer.as_mut_ptr());
+
+        let mut value = CStr::from_ptr(buffer.as_ptr())
+            .to_string_lossy()
+            .to_string();
+        if length > bounded_length {
+            value.push('…');
+        }
+
+        Some(value)
     }
 }
 
@@ -0,0 +1,131 @@
+//! Allocations aggregated by class and by the site that allocated them.
+
+use std::{collections::HashMap, hash::Hash};
+
+use crate::{AllocationEvent, MethodLocation, class::ClassIdentifier};
+
+/// Allocations grouped by `key`. Sampled allocations stand for about a sampling interval of
+/// bytes each, so counts and sizes are relative, not absolute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allocations<K> {
+    pub key: K,
+    pub count: usize,
+    pub bytes: i64,
+}
+
+/// Allocations per class, most bytes first.
+pub fn by_class<'a>(
+    events: impl IntoIterator<Item = &'a AllocationEvent>,
+) -> Vec<Allocations<ClassIdentifier>> {
+    aggregate(events, |event| event.class_identifier.clone())
+}
+
+/// Allocations per innermost frame, most bytes first. The site is `None` for allocations
+/// without Java frames, e.g. by the VM while starting up.
+pub fn by_site<'a>(
+    events: impl IntoIterator<Item = &'a AllocationEvent>,
+) -> Vec<Allocations<Option<MethodLocation>>> {
+    aggregate(events, |event| event.stack_trace.first().cloned())
+}
+
+fn aggregate<'a, K: Eq + Hash + Clone>(
+    events: impl IntoIterator<Item = &'a AllocationEvent>,
+    key: impl Fn(&AllocationEvent) -> K,
+) -> Vec<Allocations<K>> {
+    let mut indices: HashMap<K, usize> = HashMap::new();
+    let mut allocations: Vec<Allocations<K>> = Vec::new();
+    for event in events {
+        let key = key(event);
+        let index = *indices.entry(key.clone()).or_insert_with(|| {
+            allocations.push(Allocations {
+                key,
+                count: 0,
+                bytes: 0,
+            });
+            allocations.len() - 1
+        });
+
+        allocations[index].count += 1;
+        allocations[index].bytes += event.size;
+    }
+
+    // stable, so ties stay in order of their first allocation
+    allocations.sort_by_key(|a| std::cmp::Reverse(a.bytes));
+    allocations
+}
+
+/// Formats a size in bytes with a binary unit that fits its magnitude.
+pub fn format_bytes(bytes: i64) -> String {
+    if bytes.abs() >= 1 << 30 {
+        format!("{:.2} GiB", bytes as f64 / (1 << 30) as f64)
+    } else if bytes.abs() >= 1 << 20 {
+        format!("{:.2} MiB", bytes as f64 / (1 << 20) as f64)
+    } else if bytes.abs() >= 1 << 10 {
+        format!("{:.2} KiB", bytes as f64 / (1 << 10) as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AllocationEventType, descriptor::MethodDescriptor, test_support::thread};
+
+    fn site(name: &str, line_number: i32) -> MethodLocation {
+        MethodLocation {
+            class_identifier: ClassIdentifier::parse("com.acme.Service").unwrap(),
+            name: name.to_string(),
+            descriptor: MethodDescriptor::new("()V").unwrap(),
+            location: 0,
+            line_number: Some(line_number),
+        }
+    }
+
+    fn allocation(class: &str, size: i64, stack_trace: Vec<MethodLocation>) -> AllocationEvent {
+        AllocationEvent {
+            timestamp: 0,
+            allocation_event_type: AllocationEventType::Sampled,
+            class_identifier: ClassIdentifier::parse(class).unwrap(),
+            size,
+            thread: thread(1),
+            stack_trace,
+        }
+    }
+
+    #[test]
+    fn aggregates_by_class_and_site() {
+        let events = vec![
+            allocation("[B", 1024, vec![site("read", 10), site("handle", 3)]),
+            allocation("[B", 2048, vec![site("read", 10)]),
+            allocation("java.lang.String", 24, vec![site("read", 12)]),
+            allocation("java.lang.String", 24, Vec::new()),
+        ];
+
+        let classes: Vec<(String, usize, i64)> = by_class(&events)
+            .into_iter()
+            .map(|a| (a.key.to_string(), a.count, a.bytes))
+            .collect();
+        assert_eq!(
+            classes,
+            [
+                ("byte[]".to_string(), 2, 3072),
+                ("java.lang.String".to_string(), 2, 48)
+            ]
+        );
+
+        let sites = by_site(&events);
+        assert_eq!(sites.len(), 3);
+        assert_eq!(sites[0].key, Some(site("read", 10)));
+        assert_eq!((sites[0].count, sites[0].bytes), (2, 3072));
+        assert_eq!(sites[1].key, Some(site("read", 12)));
+        assert_eq!(sites[2].key, None);
+    }
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.50 KiB");
+        assert_eq!(format_bytes(3 << 20), "3.00 MiB");
+    }
+}
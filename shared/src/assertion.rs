@@ -0,0 +1,452 @@
+//! Conditions on a run from the `assertions` of the config, checked after headless runs so Aida
+//! can guard call counts, latencies and class loads in CI.
+
+use std::fmt::Display;
+
+use crate::{
+    AssertionConfig, ClassLoadEvent, MethodConfig, MethodEvent, MethodEventType,
+    call_tree::CallTree,
+    format_duration,
+    matcher::{MethodPattern, Pattern},
+    stats::{self, Invocation, MethodStats},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionError {
+    /// Position of the assertion in the config, starting at 1.
+    pub index: usize,
+    pub reason: String,
+}
+
+impl Display for AssertionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid assertion {}: {}", self.index, self.reason)
+    }
+}
+
+impl std::error::Error for AssertionError {}
+
+#[derive(Debug)]
+enum Target {
+    Class(Pattern),
+    Method(MethodPattern),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Check {
+    MaxCalls(usize),
+    MinCalls(usize),
+    /// Latency at the percentile in microseconds.
+    MaxLatency {
+        percentile: f64,
+        micros: i64,
+    },
+    NeverLoads,
+}
+
+/// Compiled form of an [`AssertionConfig`].
+#[derive(Debug)]
+pub struct Assertion {
+    /// What the assertion is about as written in the config, e.g. `com.acme.Service.handle`.
+    target_name: String,
+    target: Target,
+    checks: Vec<Check>,
+}
+
+/// Outcome of one bound of an [`Assertion`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionResult {
+    pub description: String,
+    /// What was measured, e.g. `312 calls`.
+    pub actual: String,
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Report {
+    pub results: Vec<AssertionResult>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed)
+    }
+
+    pub fn failures(&self) -> usize {
+        self.results.iter().filter(|r| !r.passed).count()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for result in &self.results {
+            writeln!(
+                f,
+                "{}  {} ({})",
+                if result.passed { "PASS" } else { "FAIL" },
+                result.description,
+                result.actual
+            )?;
+        }
+
+        match self.failures() {
+            0 => write!(f, "all {} assertions passed", self.results.len()),
+            failures => write!(
+                f,
+                "{} of {} assertions failed",
+                failures,
+                self.results.len()
+            ),
+        }
+    }
+}
+
+impl Assertion {
+    /// Compiles the assertions of a config, errors name the position of the assertion.
+    pub fn compile(configs: &[AssertionConfig]) -> Result<Vec<Self>, AssertionError> {
+        configs
+            .iter()
+            .enumerate()
+            .map(|(index, config)| {
+                Self::new(config).map_err(|reason| AssertionError {
+                    index: index + 1,
+                    reason,
+                })
+            })
+            .collect()
+    }
+
+    fn new(config: &AssertionConfig) -> Result<Self, String> {
+        let (target_name, target) = match &config.method {
+            Some(method) => (
+                format!(
+                    "{}.{}{}",
+                    config.class,
+                    method,
+                    config.descriptor.as_deref().unwrap_or_default()
+                ),
+                Target::Method(
+                    MethodPattern::new(&MethodConfig {
+                        name: method.clone(),
+                        class: config.class.clone(),
+                        descriptor: config.descriptor.clone(),
+                    })
+                    .map_err(|err| err.to_string())?,
+                ),
+            ),
+            None => (
+                config.class.clone(),
+                Target::Class(Pattern::parse(&config.class).map_err(|err| err.to_string())?),
+            ),
+        };
+
+        let mut checks = Vec::new();
+        if let Some(max_calls) = config.max_calls {
+            checks.push(Check::MaxCalls(max_calls));
+        }
+        if let Some(min_calls) = config.min_calls {
+            checks.push(Check::MinCalls(min_calls));
+        }
+        match (&config.max_latency, config.percentile) {
+            (Some(max_latency), percentile) => {
+                let percentile = percentile.unwrap_or(100.0);
+                if !(0.0..=100.0).contains(&percentile) {
+                    return Err(format!(
+                        "percentile {} is not between 0 and 100",
+                        percentile
+                    ));
+                }
+
+                checks.push(Check::MaxLatency {
+                    percentile,
+                    micros: parse_duration(max_latency)?,
+                });
+            }
+            (None, Some(_)) => return Err("percentile needs a max_latency".to_string()),
+            (None, None) => {}
+        }
+        if config.never_load {
+            checks.push(Check::NeverLoads);
+        }
+
+        if checks.is_empty() {
+            return Err("nothing to check".to_string());
+        }
+        match target {
+            Target::Class(_) if checks.iter().any(|c| *c != Check::NeverLoads) => {
+                Err("calls and latencies need a method".to_string())
+            }
+            Target::Method(_) if checks.contains(&Check::NeverLoads) => {
+                Err("never_load needs a class without a method".to_string())
+            }
+            _ => Ok(Self {
+                target_name,
+                target,
+                checks,
+            }),
+        }
+    }
+
+    fn description(&self, check: Check) -> String {
+        match check {
+            Check::MaxCalls(max_calls) => {
+                format!("{} is called at most {} times", self.target_name, max_calls)
+            }
+            Check::MinCalls(min_calls) => {
+                format!(
+                    "{} is called at least {} times",
+                    self.target_name, min_calls
+                )
+            }
+            Check::MaxLatency {
+                percentile: 100.0,
+                micros,
+            } => format!(
+                "{} always returns within {}",
+                self.target_name,
+                format_duration(micros)
+            ),
+            Check::MaxLatency { percentile, micros } => format!(
+                "{} p{} is at most {}",
+                self.target_name,
+                percentile,
+                format_duration(micros)
+            ),
+            Check::NeverLoads => format!("class {} is never loaded", self.target_name),
+        }
+    }
+
+    fn matches_method(&self, method_event: &MethodEvent) -> bool {
+        match &self.target {
+            Target::Method(pattern) => pattern.matches(
+                &method_event.name,
+                &method_event.class_identifier.to_string(),
+                &method_event.descriptor,
+            ),
+            Target::Class(_) => false,
+        }
+    }
+
+    fn matches_class(&self, class_load_event: &ClassLoadEvent) -> bool {
+        match &self.target {
+            Target::Class(pattern) => {
+                pattern.matches(&class_load_event.class_identifier.to_string())
+            }
+            Target::Method(_) => false,
+        }
+    }
+
+    /// Returns whether the check holds and what was measured.
+    fn check(
+        &self,
+        check: Check,
+        class_load_events: &[ClassLoadEvent],
+        method_events: &[MethodEvent],
+        method_stats: &[MethodStats],
+    ) -> (bool, String) {
+        match check {
+            Check::MaxCalls(_) | Check::MinCalls(_) => {
+                // invocations that have not returned count as well
+                let calls = method_events
+                    .iter()
+                    .filter(|e| matches!(e.method_event_type, MethodEventType::Entry))
+                    .filter(|e| self.matches_method(e))
+                    .count();
+                let passed = match check {
+                    Check::MaxCalls(max_calls) => calls <= max_calls,
+                    Check::MinCalls(min_calls) => calls >= min_calls,
+                    _ => unreachable!(),
+                };
+
+                (passed, format!("{} calls", calls))
+            }
+            Check::MaxLatency { percentile, micros } => {
+                let mut invocations: Vec<Invocation> = method_stats
+                    .iter()
+                    .filter(|stats| self.matches_method(&method_events[stats.invocations[0].entry]))
+                    .flat_map(|stats| stats.invocations.iter().cloned())
+                    .collect();
+                invocations.sort_by_key(|invocation| invocation.duration);
+
+                // a bound on latencies holds for methods that never returned
+                if invocations.is_empty() {
+                    return (true, "no finished invocations".to_string());
+                }
+
+                let latency = stats::percentile(&invocations, percentile);
+                (
+                    latency <= micros,
+                    format!(
+                        "{} over {} invocations",
+                        format_duration(latency),
+                        invocations.len()
+                    ),
+                )
+            }
+            Check::NeverLoads => {
+                let loads: Vec<&ClassLoadEvent> = class_load_events
+                    .iter()
+                    .filter(|e| self.matches_class(e))
+                    .collect();
+
+                match loads.first() {
+                    Some(first) => (
+                        false,
+                        format!(
+                            "loaded {} times, first {} on {}",
+                            loads.len(),
+                            first.class_identifier,
+                            first.thread.name
+                        ),
+                    ),
+                    None => (true, "not loaded".to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Checks every assertion against the events of a run.
+pub fn check(
+    assertions: &[Assertion],
+    class_load_events: &[ClassLoadEvent],
+    method_events: &[MethodEvent],
+) -> Report {
+    let call_tree = CallTree::build(method_events);
+    let method_stats = MethodStats::collect(&call_tree, method_events);
+
+    let results = assertions
+        .iter()
+        .flat_map(|assertion| {
+            assertion.checks.iter().map(|&check| {
+                let (passed, actual) =
+                    assertion.check(check, class_load_events, method_events, &method_stats);
+
+                AssertionResult {
+                    description: assertion.description(check),
+                    actual,
+                    passed,
+                }
+            })
+        })
+        .collect();
+
+    Report { results }
+}
+
+/// Parses durations like `20ms`, `1.5s` or `500us` into microseconds.
+pub fn parse_duration(raw: &str) -> Result<i64, String> {
+    let raw = raw.trim();
+    let unit_start = raw
+        .find(|c: char| c.is_alphabetic())
+        .ok_or_else(|| format!("duration '{}' has no unit", raw))?;
+    let (value, unit) = raw.split_at(unit_start);
+
+    let value: f64 = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", raw))?;
+    let factor = match unit {
+        "us" | "µs" => 1.0,
+        "ms" => 1_000.0,
+        "s" => 1_000_000.0,
+        _ => return Err(format!("unknown unit '{}' in duration '{}'", unit, raw)),
+    };
+
+    Ok((value * factor).round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{class_load_event, method_event};
+
+    fn assertions(toml: &str) -> Result<Vec<Assertion>, AssertionError> {
+        #[derive(serde::Deserialize)]
+        struct Assertions {
+            assertions: Vec<AssertionConfig>,
+        }
+
+        Assertion::compile(&toml::from_str::<Assertions>(toml).unwrap().assertions)
+    }
+
+    #[test]
+    fn checks_calls_latencies_and_class_loads() {
+        let assertions = assertions(
+            r#"
+            [[assertions]]
+            class = 'com.acme.Service'
+            method = 'handle'
+            max_calls = 2
+            percentile = 50
+            max_latency = '20us'
+
+            [[assertions]]
+            class = 'com.acme.*'
+            method = 'handle'
+            max_latency = '20us'
+
+            [[assertions]]
+            class = 'com.acme.Legacy*'
+            never_load = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(assertions.len(), 3);
+
+        let method_events = vec![
+            method_event(0, "handle", MethodEventType::Entry),
+            method_event(10, "handle", MethodEventType::Exit),
+            method_event(100, "handle", MethodEventType::Entry),
+            method_event(130, "handle", MethodEventType::Exit),
+            method_event(200, "other", MethodEventType::Entry),
+        ];
+        let class_load_events = vec![class_load_event("com.acme.LegacyCache")];
+
+        let report = check(&assertions, &class_load_events, &method_events);
+        let passed: Vec<bool> = report.results.iter().map(|r| r.passed).collect();
+        assert_eq!(passed, [true, true, false, false]);
+        assert_eq!(report.results[0].actual, "2 calls");
+        assert_eq!(report.results[1].actual, "10 µs over 2 invocations");
+        assert_eq!(
+            report.results[1].description,
+            "com.acme.Service.handle p50 is at most 20 µs"
+        );
+        assert_eq!(
+            report.results[3].actual,
+            "loaded 1 times, first com.acme.LegacyCache on thread-1"
+        );
+        assert!(!report.passed());
+        assert!(report.to_string().ends_with("2 of 4 assertions failed"));
+    }
+
+    #[test]
+    fn rejects_invalid_assertions() {
+        let err = assertions(
+            r#"
+            [[assertions]]
+            class = 'com.acme.Service'
+            never_load = true
+
+            [[assertions]]
+            class = 'com.acme.Service'
+            max_calls = 1
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(err.index, 2);
+
+        assert!(assertions("[[assertions]]\nclass = 'X'\nmethod = 'run'").is_err());
+        assert!(
+            assertions("[[assertions]]\nclass = 'X'\nmethod = 'run'\nmax_latency = '5'").is_err()
+        );
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("20ms"), Ok(20_000));
+        assert_eq!(parse_duration("1.5 s"), Ok(1_500_000));
+        assert_eq!(parse_duration("250µs"), Ok(250));
+        assert!(parse_duration("3h").is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{MethodEvent, MethodEventType, ThreadInfo};
+
+/// Method invocations reconstructed from paired entry and exit events.
+#[derive(Debug, Default)]
+pub struct CallTree {
+    pub threads: Vec<ThreadCallTree>,
+}
+
+#[derive(Debug)]
+pub struct ThreadCallTree {
+    pub thread: ThreadInfo,
+    pub roots: Vec<CallNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallNode {
+    /// Index of the entry event in the events the tree was built from.
+    pub entry: usize,
+    /// Index of the exit event, `None` if the method has not returned (yet).
+    pub exit: Option<usize>,
+    pub start: i64,
+    pub end: Option<i64>,
+    pub children: Vec<CallNode>,
+}
+
+impl CallNode {
+    /// Time spent in the method including its callees, in microseconds.
+    pub fn inclusive_time(&self) -> Option<i64> {
+        self.end.map(|end| end - self.start)
+    }
+
+    /// Time spent in the method itself, excluding its traced callees, in microseconds.
+    pub fn exclusive_time(&self) -> Option<i64> {
+        let children: i64 = self
+            .children
+            .iter()
+            .filter_map(|child| child.inclusive_time())
+            .sum();
+
+        self.inclusive_time().map(|inclusive| inclusive - children)
+    }
+}
+
+impl CallTree {
+    pub fn build(events: &[MethodEvent]) -> Self {
+        let mut builders: Vec<(ThreadCallTree, Vec<CallNode>)> = Vec::new();
+
+        for (index, event) in events.iter().enumerate() {
+            let position = match builders
+                .iter()
+                .position(|(t, _)| t.thread.id == event.thread.id)
+            {
+                Some(position) => position,
+                None => {
+                    builders.push((
+                        ThreadCallTree {
+                            thread: event.thread.clone(),
+                            roots: Vec::new(),
+                        },
+                        Vec::new(),
+                    ));
+                    builders.len() - 1
+                }
+            };
+            let (tree, stack) = &mut builders[position];
+
+            match event.method_event_type {
+                MethodEventType::Entry => stack.push(CallNode {
+                    entry: index,
+                    exit: None,
+                    start: event.timestamp,
+                    end: None,
+                    children: Vec::new(),
+                }),
+                MethodEventType::Exit => {
+                    // exits without a matching entry, e.g. from calls that started before
+                    // tracing, are dropped
+                    let Some(matching) = stack
+                        .iter()
+                        .rposition(|node| events[node.entry].is_same_method(event))
+                    else {
+                        continue;
+                    };
+
+                    // frames above the matching one never reported an exit, they were
+                    // unwound together with it
+                    while stack.len() > matching + 1 {
+                        let mut node = stack.pop().unwrap();
+                        node.end = Some(event.timestamp);
+                        attach(tree, stack, node);
+                    }
+
+                    let mut node = stack.pop().unwrap();
+                    node.exit = Some(index);
+                    node.end = Some(event.timestamp);
+                    attach(tree, stack, node);
+                }
+            }
+        }
+
+        let threads = builders
+            .into_iter()
+            .map(|(mut tree, mut stack)| {
+                while let Some(node) = stack.pop() {
+                    attach(&mut tree, &mut stack, node);
+                }
+
+                tree
+            })
+            .collect();
+
+        Self { threads }
+    }
+}
+
+fn attach(tree: &mut ThreadCallTree, stack: &mut [CallNode], node: CallNode) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(node),
+        None => tree.roots.push(node),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::method_event;
+
+    #[test]
+    fn nests_calls_and_computes_times() {
+        let events = vec![
+            method_event(0, "outer", MethodEventType::Entry),
+            method_event(10, "inner", MethodEventType::Entry),
+            method_event(40, "inner", MethodEventType::Exit),
+            method_event(100, "outer", MethodEventType::Exit),
+        ];
+
+        let tree = CallTree::build(&events);
+        assert_eq!(tree.threads.len(), 1);
+
+        let roots = &tree.threads[0].roots;
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].exit, Some(3));
+        assert_eq!(roots[0].inclusive_time(), Some(100));
+        assert_eq!(roots[0].exclusive_time(), Some(70));
+        assert_eq!(roots[0].children.len(), 1);
+        assert_eq!(roots[0].children[0].inclusive_time(), Some(30));
+    }
+
+    #[test]
+    fn separates_threads() {
+        let events = vec![
+            method_event(0, "a", MethodEventType::Entry),
+            method_event(5, "b", MethodEventType::Entry).on_thread(2),
+            method_event(10, "a", MethodEventType::Exit),
+            method_event(20, "b", MethodEventType::Exit).on_thread(2),
+        ];
+
+        let tree = CallTree::build(&events);
+        assert_eq!(tree.threads.len(), 2);
+        assert_eq!(tree.threads[0].roots[0].inclusive_time(), Some(10));
+        assert_eq!(tree.threads[1].roots[0].inclusive_time(), Some(15));
+        assert!(tree.threads[1].roots[0].children.is_empty());
+    }
+
+    #[test]
+    fn handles_unmatched_events() {
+        let events = vec![
+            method_event(0, "orphan", MethodEventType::Exit),
+            method_event(10, "outer", MethodEventType::Entry),
+            method_event(20, "unwound", MethodEventType::Entry),
+            method_event(30, "outer", MethodEventType::Exit),
+            method_event(40, "running", MethodEventType::Entry),
+        ];
+
+        let tree = CallTree::build(&events);
+        let roots = &tree.threads[0].roots;
+        assert_eq!(roots.len(), 2);
+
+        assert_eq!(roots[0].inclusive_time(), Some(20));
+        assert_eq!(roots[0].children[0].exit, None);
+        assert_eq!(roots[0].children[0].inclusive_time(), Some(10));
+
+        assert_eq!(roots[1].end, None);
+        assert_eq!(roots[1].inclusive_time(), None);
+    }
+}
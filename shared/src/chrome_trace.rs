@@ -0,0 +1,221 @@
+//! Export to the [Chrome Trace Event format], which ui.perfetto.dev and chrome://tracing open
+//! directly.
+//!
+//! Every thread gets its own track. Finished invocations become complete ("X") events,
+//! invocations that never returned become begin ("B") events without a matching end, so viewers
+//! show them as running until the end of the trace. Class loads become instant ("i") events on
+//! the track of the loading thread.
+//!
+//! [Chrome Trace Event format]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue, json};
+
+use crate::{
+    ClassLoadEvent, MethodEvent, ThreadInfo,
+    call_tree::{CallNode, CallTree},
+};
+
+/// All events belong to the traced JVM.
+const PID: u32 = 1;
+
+#[derive(Serialize, Debug, PartialEq)]
+struct TraceEvent {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cat: Option<&'static str>,
+    ph: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ts: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<i64>,
+    pid: u32,
+    tid: u64,
+    /// Scope of instant events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    s: Option<&'static str>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    args: Map<String, JsonValue>,
+}
+
+/// Writes the events as a JSON trace, timestamps stay in microseconds since the epoch.
+pub fn write(
+    out: impl Write,
+    class_load_events: &[ClassLoadEvent],
+    method_events: &[MethodEvent],
+) -> serde_json::Result<()> {
+    let trace = json!({
+        "traceEvents": trace_events(class_load_events, method_events),
+        "displayTimeUnit": "ms",
+    });
+
+    serde_json::to_writer(out, &trace)
+}
+
+fn trace_events(
+    class_load_events: &[ClassLoadEvent],
+    method_events: &[MethodEvent],
+) -> Vec<TraceEvent> {
+    let mut threads: Vec<&ThreadInfo> = Vec::new();
+    for thread in method_events
+        .iter()
+        .map(|e| &e.thread)
+        .chain(class_load_events.iter().map(|e| &e.thread))
+    {
+        if !threads.iter().any(|t| t.id == thread.id) {
+            threads.push(thread);
+        }
+    }
+
+    let mut trace_events: Vec<TraceEvent> = threads
+        .iter()
+        .map(|thread| TraceEvent {
+            name: "thread_name".to_string(),
+            cat: None,
+            ph: "M",
+            ts: None,
+            dur: None,
+            pid: PID,
+            tid: thread.id,
+            s: None,
+            args: Map::from_iter([("name".to_string(), json!(thread.name))]),
+        })
+        .collect();
+
+    let call_tree = CallTree::build(method_events);
+    for thread_call_tree in &call_tree.threads {
+        for node in &thread_call_tree.roots {
+            push_invocations(&mut trace_events, node, method_events);
+        }
+    }
+
+    for class_load_event in class_load_events {
+        trace_events.push(TraceEvent {
+            name: format!("load {}", class_load_event.class_identifier.short_name()),
+            cat: Some("class_load"),
+            ph: "i",
+            ts: Some(class_load_event.timestamp),
+            dur: None,
+            pid: PID,
+            tid: class_load_event.thread.id,
+            s: Some("t"),
+            args: Map::from_iter([(
+                "class".to_string(),
+                json!(class_load_event.class_identifier.to_string()),
+            )]),
+        });
+    }
+
+    trace_events
+}
+
+fn push_invocations(trace_events: &mut Vec<TraceEvent>, node: &CallNode, events: &[MethodEvent]) {
+    let entry = &events[node.entry];
+
+    let mut args = Map::new();
+    args.insert(
+        "class".to_string(),
+        json!(entry.class_identifier.to_string()),
+    );
+    args.insert(
+        "descriptor".to_string(),
+        json!(entry.descriptor.to_descriptor()),
+    );
+    if !entry.arguments.is_empty() {
+        let arguments: Vec<String> = entry.arguments.iter().map(|a| a.to_string()).collect();
+        args.insert("arguments".to_string(), json!(arguments));
+    }
+    if let Some(exit) = node.exit.map(|exit| &events[exit]) {
+        if let Some(return_value) = &exit.return_value {
+            args.insert("return_value".to_string(), json!(return_value.to_string()));
+        }
+        if exit.was_popped_by_exception {
+            args.insert("threw".to_string(), json!(true));
+        }
+    }
+
+    trace_events.push(TraceEvent {
+        name: format!(
+            "{}.{}{}",
+            entry.class_identifier.short_name(),
+            entry.name,
+            entry.descriptor.to_short_string()
+        ),
+        cat: Some("method"),
+        ph: if node.end.is_some() { "X" } else { "B" },
+        ts: Some(node.start),
+        dur: node.inclusive_time(),
+        pid: PID,
+        tid: entry.thread.id,
+        s: None,
+        args,
+    });
+
+    for child in &node.children {
+        push_invocations(trace_events, child, events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MethodEventType,
+        class::ClassIdentifier,
+        test_support::{method_event, thread},
+    };
+
+    #[test]
+    fn pairs_invocations_per_thread() {
+        let method_events = vec![
+            method_event(100, "outer", MethodEventType::Entry),
+            method_event(110, "inner", MethodEventType::Entry).with_descriptor("(I)V"),
+            method_event(150, "inner", MethodEventType::Exit).with_descriptor("(I)V"),
+            method_event(200, "running", MethodEventType::Entry),
+        ];
+        let class_load_events = vec![ClassLoadEvent {
+            timestamp: 105,
+            class_identifier: ClassIdentifier::parse("com.acme.Outer$Inner").unwrap(),
+            signature: None,
+            thread: thread(2),
+        }];
+
+        let trace_events = trace_events(&class_load_events, &method_events);
+        let phases: Vec<&str> = trace_events.iter().map(|e| e.ph).collect();
+        assert_eq!(phases, ["M", "M", "B", "X", "B", "i"]);
+
+        assert_eq!(trace_events[0].args["name"], "thread-1");
+        assert_eq!(trace_events[1].tid, 2);
+
+        let inner = &trace_events[3];
+        assert_eq!(inner.name, "Service.inner(int)");
+        assert_eq!(inner.ts, Some(110));
+        assert_eq!(inner.dur, Some(40));
+        assert_eq!(inner.args["descriptor"], "(I)V");
+
+        let class_load = &trace_events[5];
+        assert_eq!(class_load.name, "load Outer.Inner");
+        assert_eq!(class_load.tid, 2);
+        assert_eq!(class_load.s, Some("t"));
+    }
+
+    #[test]
+    fn writes_trace_object() {
+        let method_events = vec![
+            method_event(0, "run", MethodEventType::Entry),
+            method_event(10, "run", MethodEventType::Exit),
+        ];
+
+        let mut out = Vec::new();
+        write(&mut out, &[], &method_events).unwrap();
+
+        let trace: JsonValue = serde_json::from_slice(&out).unwrap();
+        let trace_events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(trace_events.len(), 2);
+        assert_eq!(trace_events[1]["ph"], "X");
+        assert_eq!(trace_events[1]["dur"], 10);
+        assert!(trace_events[1].get("s").is_none());
+    }
+}
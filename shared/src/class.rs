@@ -1,81 +1,297 @@
-use std::fmt::Display;
+use std::{borrow::Cow, fmt::Display};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+use crate::descriptor::{BaseType, DescriptorError, FieldType};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ClassIdentifier {
     package: Vec<String>,
+    /// Simple binary name as in the class file, e.g. `Outer$Inner`.
     name: String,
+    /// Enclosing classes, outermost first.
+    outer: Vec<ClassName>,
+    class_name: ClassName,
+    /// Unique suffix the JVM appends to the names of hidden classes.
+    hidden_suffix: Option<String>,
+    /// Number of dimensions for array classes, the other fields describe the element class.
+    array_dimensions: u8,
+}
+
+/// One class in the chain of enclosing classes, as the Java source would name it.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClassName {
+    Named(String),
+    /// Anonymous class with the index javac assigned within its outermost class.
+    Anonymous(u32),
+    Lambda,
+    /// Element type of primitive arrays like `int[]`.
+    Primitive(BaseType),
+}
+
+impl Display for ClassName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClassName::Named(name) => write!(f, "{}", name),
+            ClassName::Anonymous(index) => write!(f, "<anon {}>", index),
+            ClassName::Lambda => write!(f, "λ"),
+            ClassName::Primitive(base_type) => write!(f, "{}", base_type),
+        }
+    }
 }
 
 impl ClassIdentifier {
-    pub fn parse(raw: &str) -> Self {
-        let raw = raw.replace("/", ".").replace(";", "");
-        let raw: String = raw.chars().skip_while(|c| *c == 'L' || *c == '[').collect();
-
-        match raw.as_str() {
-            "B" => {
-                return Self {
-                    package: vec!["java".to_string(), "lang".to_string()],
-                    name: "Byte".to_owned(),
-                };
-            }
-            "C" => {
-                return Self {
-                    package: vec!["java".to_string(), "lang".to_string()],
-                    name: "Character".to_owned(),
-                };
-            }
-            "D" => {
-                return Self {
-                    package: vec!["java".to_string(), "lang".to_string()],
-                    name: "Double".to_owned(),
-                };
-            }
-            "F" => {
-                return Self {
-                    package: vec!["java".to_string(), "lang".to_string()],
-                    name: "Float".to_owned(),
-                };
-            }
-            "I" => {
-                return Self {
-                    package: vec!["java".to_string(), "lang".to_string()],
-                    name: "Integer".to_owned(),
-                };
-            }
-            "J" => {
-                return Self {
-                    package: vec!["java".to_string(), "lang".to_string()],
-                    name: "Long".to_owned(),
-                };
-            }
-            "S" => {
-                return Self {
-                    package: vec!["java".to_string(), "lang".to_string()],
-                    name: "Short".to_owned(),
-                };
+    pub fn new(package: Vec<String>, name: String) -> Self {
+        let (outer, class_name) = parse_nesting(&name);
+
+        Self {
+            package,
+            name,
+            outer,
+            class_name,
+            hidden_suffix: None,
+            array_dimensions: 0,
+        }
+    }
+
+    /// Parses a class name in binary (`java.lang.String`) or internal (`java/lang/String`)
+    /// form, or a class signature like `Ljava/lang/String;` or `[I`.
+    ///
+    /// Hidden classes may carry their suffix in either form, `Foo$$Lambda/0x1234` or
+    /// `Foo$$Lambda.0x1234`.
+    pub fn parse(raw: &str) -> Result<Self, DescriptorError> {
+        if raw.starts_with('[') {
+            return match FieldType::new(raw)? {
+                FieldType::Array {
+                    element_type,
+                    dimensions,
+                } => Ok(match *element_type {
+                    FieldType::Object { class_identifier } => Self {
+                        array_dimensions: dimensions,
+                        ..class_identifier
+                    },
+                    FieldType::Base(base_type) => Self {
+                        package: Vec::new(),
+                        name: base_type.to_string(),
+                        outer: Vec::new(),
+                        class_name: ClassName::Primitive(base_type),
+                        hidden_suffix: None,
+                        array_dimensions: dimensions,
+                    },
+                    FieldType::Array { .. } => {
+                        unreachable!("array element types are never arrays")
+                    }
+                }),
+                _ => unreachable!("field types starting with '[' are arrays"),
+            };
+        }
+
+        let (offset, class_name) = match raw.strip_prefix('L').and_then(|r| r.strip_suffix(';')) {
+            Some(class_name) => (1, class_name),
+            None => (0, raw),
+        };
+
+        if class_name.is_empty() {
+            return Err(DescriptorError::new(offset, "empty class name"));
+        }
+        if let Some(position) = class_name.find([';', '[']) {
+            return Err(DescriptorError::new(
+                offset + position,
+                format!("invalid character in class name '{class_name}'"),
+            ));
+        }
+
+        let (class_name, hidden_suffix) = match class_name.rsplit_once(['.', '/']) {
+            Some((class_name, suffix)) if is_hidden_suffix(suffix) => {
+                (class_name, Some(suffix.to_string()))
             }
-            "Z" => {
-                return Self {
-                    package: vec!["java".to_string(), "lang".to_string()],
-                    name: "Boolean".to_owned(),
-                };
+            _ => (class_name, None),
+        };
+
+        let mut parts = Vec::new();
+        let mut part_offset = offset;
+        for part in class_name.split(['.', '/']) {
+            if part.is_empty() {
+                return Err(DescriptorError::new(
+                    part_offset,
+                    format!("empty package or class name in '{class_name}'"),
+                ));
             }
-            _ => {}
+
+            parts.push(part.to_string());
+            part_offset += part.len() + 1;
         }
 
-        let mut parts: Vec<&str> = raw.split('.').collect();
-        let name = parts.last().unwrap().to_string();
-        parts.truncate(parts.len() - 1);
-        let package: Vec<String> = parts.iter().map(|p| p.to_string()).collect();
+        let name = parts.pop().unwrap();
+        Ok(Self {
+            hidden_suffix,
+            ..Self::new(parts, name)
+        })
+    }
+
+    /// The name in internal form, e.g. `java/lang/String`, or the descriptor for array classes
+    /// like `[I`.
+    pub fn to_internal_name(&self) -> String {
+        if self.array_dimensions > 0 {
+            let element_type = match &self.class_name {
+                ClassName::Primitive(base_type) => base_type.to_descriptor().to_string(),
+                _ => format!("L{};", self.element().to_internal_name()),
+            };
 
-        Self { package, name }
+            return format!(
+                "{}{}",
+                "[".repeat(self.array_dimensions as usize),
+                element_type
+            );
+        }
+
+        let mut internal_name = String::new();
+        for package in &self.package {
+            internal_name.push_str(package);
+            internal_name.push('/');
+        }
+
+        internal_name.push_str(&self.name);
+        if let Some(hidden_suffix) = &self.hidden_suffix {
+            internal_name.push('.');
+            internal_name.push_str(hidden_suffix);
+        }
+
+        internal_name
     }
 
+    /// Simple binary name, e.g. `Outer$Inner` or `int` for `int[]`.
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Name as it reads in Java source, e.g. `Outer.Inner`, `Outer.<anon 1>` or `λ in Outer`.
+    pub fn short_name(&self) -> String {
+        let outer: Vec<String> = self.outer.iter().map(|c| c.to_string()).collect();
+
+        let mut short_name = match (&self.class_name, outer.is_empty()) {
+            (ClassName::Lambda, false) => format!("λ in {}", outer.join(".")),
+            (class_name, true) => class_name.to_string(),
+            (class_name, false) => format!("{}.{}", outer.join("."), class_name),
+        };
+
+        if self.hidden_suffix.is_some() && self.class_name != ClassName::Lambda {
+            short_name.push_str(" (hidden)");
+        }
+
+        short_name.push_str(&"[]".repeat(self.array_dimensions as usize));
+        short_name
+    }
+
+    pub fn package(&self) -> &[String] {
+        &self.package
+    }
+
+    pub fn outer_classes(&self) -> &[ClassName] {
+        &self.outer
+    }
+
+    pub fn class_name(&self) -> &ClassName {
+        &self.class_name
+    }
+
+    pub fn anonymous_index(&self) -> Option<u32> {
+        match self.class_name {
+            ClassName::Anonymous(index) => Some(index),
+            _ => None,
+        }
+    }
+
+    pub fn is_lambda(&self) -> bool {
+        self.class_name == ClassName::Lambda
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden_suffix.is_some()
+    }
+
+    /// The class without the suffix of hidden classes, which changes with every run of the JVM.
+    pub fn without_hidden_suffix(&self) -> Self {
+        Self {
+            hidden_suffix: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn array_dimensions(&self) -> u8 {
+        self.array_dimensions
+    }
+
+    /// The element class of an array class, the class itself otherwise.
+    pub fn element(&self) -> Self {
+        Self {
+            array_dimensions: 0,
+            ..self.clone()
+        }
+    }
+}
+
+/// Splits a simple binary name like `Outer$1$Inner` into its enclosing classes and the class
+/// itself. Names that don't follow javac's scheme, e.g. `$Proxy12`, are kept as they are.
+fn parse_nesting(name: &str) -> (Vec<ClassName>, ClassName) {
+    // javac names lambda classes `Outer$$Lambda`, followed by `$<index>` on older JVMs
+    if let Some(position) = name.find("$$Lambda")
+        && position > 0
+    {
+        let (mut outer, enclosing) = parse_nesting(&name[..position]);
+        outer.push(enclosing);
+        return (outer, ClassName::Lambda);
+    }
+
+    let segments: Vec<&str> = name.split('$').collect();
+    if segments.len() == 1 || segments.iter().any(|segment| segment.is_empty()) {
+        return (Vec::new(), ClassName::Named(name.to_string()));
+    }
+
+    let mut outer: Vec<ClassName> = segments
+        .iter()
+        .enumerate()
+        .map(|(i, segment)| {
+            // local classes are prefixed with an index, e.g. `Outer$1Local`
+            let local_name = segment.trim_start_matches(|c: char| c.is_ascii_digit());
+            if local_name.is_empty() {
+                segment
+                    .parse()
+                    .map(ClassName::Anonymous)
+                    .unwrap_or_else(|_| ClassName::Named(segment.to_string()))
+            } else if local_name.len() < segment.len() && i > 0 {
+                ClassName::Named(local_name.to_string())
+            } else {
+                ClassName::Named(segment.to_string())
+            }
+        })
+        .collect();
+
+    let class_name = outer.pop().unwrap();
+    (outer, class_name)
+}
+
+/// The name a class in binary form is displayed with once parsed, without parsing it, so
+/// filters can run before. Only the separator of a hidden class suffix changes, e.g.
+/// `com.acme.Proxy.0x1234` is displayed as `com.acme.Proxy/0x1234`.
+pub fn display_name(binary_name: &str) -> Cow<'_, str> {
+    match binary_name.rsplit_once(['.', '/']) {
+        Some((class_name, suffix)) if is_hidden_suffix(suffix) => {
+            Cow::Owned(format!("{}/{}", class_name, suffix))
+        }
+        _ => Cow::Borrowed(binary_name),
+    }
+}
+
+/// Hidden classes get a suffix like `0x0000000800c02460`, older JVMs used plain numbers.
+fn is_hidden_suffix(suffix: &str) -> bool {
+    let digits = suffix.strip_prefix("0x").unwrap_or(suffix);
+    !digits.is_empty()
+        && if digits.len() < suffix.len() {
+            digits.chars().all(|c| c.is_ascii_hexdigit())
+        } else {
+            digits.chars().all(|c| c.is_ascii_digit())
+        }
 }
 
 impl Display for ClassIdentifier {
@@ -84,6 +300,170 @@ impl Display for ClassIdentifier {
             write!(f, "{}.", package)?;
         }
 
-        write!(f, "{}", self.name)
+        write!(f, "{}", self.name)?;
+
+        if let Some(hidden_suffix) = &self.hidden_suffix {
+            write!(f, "/{}", hidden_suffix)?;
+        }
+
+        write!(f, "{}", "[]".repeat(self.array_dimensions as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_class_names() {
+        for raw in ["java.lang.String", "java/lang/String", "Ljava/lang/String;"] {
+            let class_identifier = ClassIdentifier::parse(raw).unwrap();
+            assert_eq!(class_identifier.name(), "String");
+            assert_eq!(class_identifier.to_string(), "java.lang.String");
+            assert_eq!(class_identifier.to_internal_name(), "java/lang/String");
+        }
+
+        let class_identifier = ClassIdentifier::parse("Main").unwrap();
+        assert_eq!(class_identifier.to_string(), "Main");
+        assert_eq!(class_identifier.to_internal_name(), "Main");
+    }
+
+    #[test]
+    fn keeps_names_that_look_like_descriptors() {
+        assert_eq!(ClassIdentifier::parse("I").unwrap().to_string(), "I");
+        assert_eq!(
+            ClassIdentifier::parse("com.acme.Logger")
+                .unwrap()
+                .to_string(),
+            "com.acme.Logger"
+        );
+        assert_eq!(
+            ClassIdentifier::parse("LLogger;").unwrap().to_string(),
+            "Logger"
+        );
+        assert_eq!(
+            ClassIdentifier::parse("Lambda").unwrap().to_string(),
+            "Lambda"
+        );
+    }
+
+    #[test]
+    fn models_nested_classes() {
+        let class_identifier = ClassIdentifier::parse("Lcom/acme/Outer$Inner;").unwrap();
+        assert_eq!(class_identifier.name(), "Outer$Inner");
+        assert_eq!(class_identifier.short_name(), "Outer.Inner");
+        assert_eq!(class_identifier.to_string(), "com.acme.Outer$Inner");
+        assert_eq!(
+            class_identifier.outer_classes(),
+            [ClassName::Named("Outer".to_string())]
+        );
+
+        let class_identifier = ClassIdentifier::parse("com.acme.Outer$1").unwrap();
+        assert_eq!(class_identifier.short_name(), "Outer.<anon 1>");
+        assert_eq!(class_identifier.anonymous_index(), Some(1));
+
+        let class_identifier = ClassIdentifier::parse("com.acme.Outer$1$Inner").unwrap();
+        assert_eq!(class_identifier.short_name(), "Outer.<anon 1>.Inner");
+        assert_eq!(class_identifier.anonymous_index(), None);
+
+        let class_identifier = ClassIdentifier::parse("com.acme.Outer$2Local").unwrap();
+        assert_eq!(class_identifier.short_name(), "Outer.Local");
+
+        for raw in ["$Proxy12", "Scala$", "A$$B"] {
+            let class_identifier = ClassIdentifier::parse(raw).unwrap();
+            assert_eq!(class_identifier.short_name(), raw);
+            assert!(class_identifier.outer_classes().is_empty());
+        }
+    }
+
+    #[test]
+    fn models_lambda_and_hidden_classes() {
+        for raw in [
+            "Lcom/acme/Outer$$Lambda.0x0000000800c02460;",
+            "com.acme.Outer$$Lambda/0x0000000800c02460",
+            "com.acme.Outer$$Lambda$14/0x0000000800c02460",
+            "com.acme.Outer$$Lambda$14/1834188994",
+        ] {
+            let class_identifier = ClassIdentifier::parse(raw).unwrap();
+            assert!(class_identifier.is_lambda(), "{raw}");
+            assert!(class_identifier.is_hidden(), "{raw}");
+            assert_eq!(class_identifier.short_name(), "λ in Outer");
+            assert_eq!(class_identifier.package(), ["com", "acme"]);
+        }
+
+        let class_identifier = ClassIdentifier::parse("Outer$Inner$$Lambda$3").unwrap();
+        assert!(class_identifier.is_lambda());
+        assert!(!class_identifier.is_hidden());
+        assert_eq!(class_identifier.short_name(), "λ in Outer.Inner");
+
+        let class_identifier = ClassIdentifier::parse("Lcom/acme/Proxy.0x1234;").unwrap();
+        assert!(!class_identifier.is_lambda());
+        assert_eq!(class_identifier.short_name(), "Proxy (hidden)");
+        assert_eq!(class_identifier.to_string(), "com.acme.Proxy/0x1234");
+        assert_eq!(class_identifier.to_internal_name(), "com/acme/Proxy.0x1234");
+    }
+
+    #[test]
+    fn display_names_without_parsing() {
+        for raw in [
+            "com.acme.Service",
+            "com.acme.Outer$Inner",
+            "com.acme.Outer$$Lambda.0x0000000800c02460",
+            "com.acme.Outer$$Lambda$14/1834188994",
+        ] {
+            assert_eq!(
+                display_name(raw),
+                ClassIdentifier::parse(raw).unwrap().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn parses_array_classes() {
+        assert_eq!(ClassIdentifier::parse("[I").unwrap().to_string(), "int[]");
+        assert_eq!(
+            ClassIdentifier::parse("[[Ljava/lang/String;")
+                .unwrap()
+                .to_string(),
+            "java.lang.String[][]"
+        );
+
+        let class_identifier = ClassIdentifier::parse("[Ljava/util/Map$Entry;").unwrap();
+        assert_eq!(class_identifier.array_dimensions(), 1);
+        assert_eq!(class_identifier.short_name(), "Map.Entry[]");
+        assert_eq!(
+            class_identifier.to_internal_name(),
+            "[Ljava/util/Map$Entry;"
+        );
+        assert_eq!(
+            class_identifier.element().to_string(),
+            "java.util.Map$Entry"
+        );
+
+        let class_identifier = ClassIdentifier::parse("[[I").unwrap();
+        assert_eq!(
+            class_identifier.class_name(),
+            &ClassName::Primitive(BaseType::Int)
+        );
+        assert_eq!(class_identifier.short_name(), "int[][]");
+        assert_eq!(class_identifier.to_internal_name(), "[[I");
+    }
+
+    #[test]
+    fn reports_invalid_class_names() {
+        assert_eq!(ClassIdentifier::parse("").unwrap_err().offset, 0);
+        assert_eq!(ClassIdentifier::parse("L;").unwrap_err().offset, 1);
+        assert_eq!(
+            ClassIdentifier::parse("java..String").unwrap_err().offset,
+            5
+        );
+        assert_eq!(
+            ClassIdentifier::parse("Ljava//String;").unwrap_err().offset,
+            6
+        );
+        assert_eq!(ClassIdentifier::parse("java.lang.").unwrap_err().offset, 10);
+        assert_eq!(ClassIdentifier::parse("java;String").unwrap_err().offset, 4);
+        assert!(ClassIdentifier::parse("[").is_err());
+        assert!(ClassIdentifier::parse("[X").is_err());
     }
 }
@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::class::ClassIdentifier;
 
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct MethodDescriptor {
     pub return_descriptor: ReturnDescriptor,
     pub parameters: Vec<FieldType>,
@@ -24,42 +24,63 @@ impl Display for MethodDescriptor {
     }
 }
 
-impl MethodDescriptor {
-    pub fn new(raw: &str) -> Self {
-        let end_of_parameter_descriptor = raw.find(")").unwrap();
+/// Error from parsing a descriptor that is not valid according to JVMS §4.3.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorError {
+    /// Byte offset in the parsed string at which parsing failed.
+    pub offset: usize,
+    pub reason: String,
+}
 
-        let mut raw_parameter_descriptor = &raw[1..end_of_parameter_descriptor];
-        let parameters = if raw_parameter_descriptor.is_empty() {
-            Vec::new()
-        } else {
-            let mut parameters = Vec::new();
-            loop {
-                let parameter = FieldType::new(raw_parameter_descriptor);
+impl DescriptorError {
+    pub(crate) fn new(offset: usize, reason: impl Into<String>) -> Self {
+        Self {
+            offset,
+            reason: reason.into(),
+        }
+    }
+}
 
-                if parameter.length() == raw_parameter_descriptor.len() {
-                    parameters.push(parameter);
-                    break;
-                }
+impl Display for DescriptorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid descriptor at offset {}: {}",
+            self.offset, self.reason
+        )
+    }
+}
 
-                raw_parameter_descriptor = &raw_parameter_descriptor[parameter.length()..];
-                parameters.push(parameter);
-            }
+impl std::error::Error for DescriptorError {}
 
-            parameters
-        };
+impl MethodDescriptor {
+    /// Parses a method descriptor like `(ILjava/lang/String;)[J`.
+    pub fn new(raw: &str) -> Result<Self, DescriptorError> {
+        let mut parser = Parser::new(raw);
+        parser.expect('(')?;
 
-        let raw_return_descriptor = &raw[end_of_parameter_descriptor + 1..];
+        let mut parameters = Vec::new();
+        loop {
+            match parser.peek() {
+                Some(')') => break,
+                Some(_) => parameters.push(parser.field_type()?),
+                None => return Err(parser.error("missing ')'")),
+            }
+        }
+        parser.expect(')')?;
 
-        let return_descriptor = if raw_return_descriptor == "V" {
+        let return_descriptor = if parser.peek() == Some('V') {
+            parser.offset += 1;
             ReturnDescriptor::Void
         } else {
-            ReturnDescriptor::FieldType(FieldType::new(raw_return_descriptor))
+            ReturnDescriptor::FieldType(parser.field_type()?)
         };
+        parser.finish()?;
 
-        Self {
+        Ok(Self {
             return_descriptor,
             parameters,
-        }
+        })
     }
 
     pub fn to_short_string(&self) -> String {
@@ -70,19 +91,49 @@ impl MethodDescriptor {
             .collect();
         format!("({})", parameters.join(", "))
     }
+
+    /// The descriptor in JVM form, e.g. `(I[Ljava/lang/String;)V`.
+    pub fn to_descriptor(&self) -> String {
+        let parameters: String = self.parameters.iter().map(|p| p.to_descriptor()).collect();
+        format!("({}){}", parameters, self.return_descriptor.to_descriptor())
+    }
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize, Serialize)]
 pub enum ReturnDescriptor {
     Void,
     FieldType(FieldType),
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+impl ReturnDescriptor {
+    pub fn to_descriptor(&self) -> String {
+        match self {
+            ReturnDescriptor::Void => "V".to_string(),
+            ReturnDescriptor::FieldType(field_type) => field_type.to_descriptor(),
+        }
+    }
+}
+
+impl Display for ReturnDescriptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReturnDescriptor::Void => write!(f, "void"),
+            ReturnDescriptor::FieldType(field_type) => write!(f, "{}", field_type),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize, Serialize)]
 pub enum FieldType {
     Base(BaseType),
-    Object { class_identifier: ClassIdentifier },
-    Component(Box<FieldType>),
+    Object {
+        class_identifier: ClassIdentifier,
+    },
+    /// The element type is never an array itself, nested arrays are counted in `dimensions`.
+    Array {
+        element_type: Box<FieldType>,
+        dimensions: u8,
+    },
 }
 
 impl Display for FieldType {
@@ -90,22 +141,74 @@ impl Display for FieldType {
         match self {
             FieldType::Base(base_type) => write!(f, "{}", base_type),
             FieldType::Object { class_identifier } => write!(f, "{}", class_identifier),
-            FieldType::Component(field_type) => write!(f, "{}", field_type),
+            FieldType::Array {
+                element_type,
+                dimensions,
+            } => write!(f, "{}{}", element_type, "[]".repeat(*dimensions as usize)),
         }
     }
 }
 
 impl FieldType {
-    fn to_short_string(&self) -> String {
+    /// An array of `element_type`, which may be an array itself. Fails with an error at offset 0
+    /// if the nested array would have more than 255 dimensions.
+    pub fn array(element_type: FieldType, dimensions: u8) -> Result<Self, DescriptorError> {
+        match element_type {
+            FieldType::Array {
+                element_type,
+                dimensions: element_dimensions,
+            } => Ok(FieldType::Array {
+                element_type,
+                dimensions: element_dimensions
+                    .checked_add(dimensions)
+                    .ok_or_else(|| DescriptorError::new(0, "too many array dimensions"))?,
+            }),
+            element_type => Ok(FieldType::Array {
+                element_type: Box::new(element_type),
+                dimensions,
+            }),
+        }
+    }
+
+    pub fn to_short_string(&self) -> String {
         match self {
             FieldType::Base(base_type) => base_type.to_string(),
-            FieldType::Object { class_identifier } => class_identifier.name().to_string(),
-            FieldType::Component(field_type) => field_type.to_string(),
+            FieldType::Object { class_identifier } => class_identifier.short_name(),
+            FieldType::Array {
+                element_type,
+                dimensions,
+            } => format!(
+                "{}{}",
+                element_type.to_short_string(),
+                "[]".repeat(*dimensions as usize)
+            ),
+        }
+    }
+
+    /// The descriptor in JVM form, e.g. `[[I` or `Ljava/lang/String;`.
+    pub fn to_descriptor(&self) -> String {
+        match self {
+            FieldType::Base(base_type) => base_type.to_descriptor().to_string(),
+            FieldType::Object { class_identifier } => {
+                format!("L{};", class_identifier.to_internal_name())
+            }
+            FieldType::Array {
+                element_type,
+                dimensions,
+            } => format!(
+                "{}{}",
+                "[".repeat(*dimensions as usize),
+                element_type.to_descriptor()
+            ),
         }
     }
+
+    pub fn is_primitive(&self) -> bool {
+        matches!(self, FieldType::Base(_))
+    }
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
 pub enum BaseType {
     Byte,
     Char,
@@ -133,29 +236,354 @@ impl Display for BaseType {
 }
 
 impl FieldType {
-    fn new(raw: &str) -> Self {
-        match &raw[0..1] {
-            "B" => Self::Base(BaseType::Byte),
-            "C" => Self::Base(BaseType::Char),
-            "D" => Self::Base(BaseType::Double),
-            "F" => Self::Base(BaseType::Float),
-            "I" => Self::Base(BaseType::Int),
-            "J" => Self::Base(BaseType::Long),
-            "S" => Self::Base(BaseType::Short),
-            "Z" => Self::Base(BaseType::Boolean),
-            "L" => Self::Object {
-                class_identifier: ClassIdentifier::parse(&raw[1..raw.find(';').unwrap()]),
-            },
-            "[" => Self::Component(Box::new(Self::new(&raw[1..]))),
-            _ => panic!("unknown field type: {raw}"),
-        }
-    }
-
-    fn length(&self) -> usize {
+    /// Parses a field descriptor like `I`, `Ljava/lang/String;` or `[[D`.
+    pub fn new(raw: &str) -> Result<Self, DescriptorError> {
+        let mut parser = Parser::new(raw);
+        let field_type = parser.field_type()?;
+        parser.finish()?;
+
+        Ok(field_type)
+    }
+}
+
+/// Arrays may have at most 255 dimensions, see JVMS §4.3.2.
+const MAX_ARRAY_DIMENSIONS: usize = 255;
+
+impl BaseType {
+    pub const ALL: [BaseType; 8] = [
+        BaseType::Byte,
+        BaseType::Char,
+        BaseType::Double,
+        BaseType::Float,
+        BaseType::Int,
+        BaseType::Long,
+        BaseType::Short,
+        BaseType::Boolean,
+    ];
+
+    pub fn to_descriptor(&self) -> char {
         match self {
-            FieldType::Base(_) => 1,
-            FieldType::Object { class_identifier } => class_identifier.to_string().len() + 2,
-            FieldType::Component(field_type) => field_type.length() + 1,
+            BaseType::Byte => 'B',
+            BaseType::Char => 'C',
+            BaseType::Double => 'D',
+            BaseType::Float => 'F',
+            BaseType::Int => 'I',
+            BaseType::Long => 'J',
+            BaseType::Short => 'S',
+            BaseType::Boolean => 'Z',
         }
     }
+
+    /// The wrapper class in `java.lang` the primitive is boxed into.
+    pub fn boxed(&self) -> ClassIdentifier {
+        let name = match self {
+            BaseType::Byte => "Byte",
+            BaseType::Char => "Character",
+            BaseType::Double => "Double",
+            BaseType::Float => "Float",
+            BaseType::Int => "Integer",
+            BaseType::Long => "Long",
+            BaseType::Short => "Short",
+            BaseType::Boolean => "Boolean",
+        };
+
+        ClassIdentifier::new(
+            vec!["java".to_string(), "lang".to_string()],
+            name.to_string(),
+        )
+    }
+
+    /// The primitive a wrapper class unboxes to, `None` for all other classes.
+    pub fn unboxed(class_identifier: &ClassIdentifier) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|base_type| base_type.boxed() == *class_identifier)
+    }
+
+    pub(crate) fn from_descriptor(c: char) -> Option<Self> {
+        match c {
+            'B' => Some(BaseType::Byte),
+            'C' => Some(BaseType::Char),
+            'D' => Some(BaseType::Double),
+            'F' => Some(BaseType::Float),
+            'I' => Some(BaseType::Int),
+            'J' => Some(BaseType::Long),
+            'S' => Some(BaseType::Short),
+            'Z' => Some(BaseType::Boolean),
+            _ => None,
+        }
+    }
+}
+
+/// Cursor over a descriptor or signature, shared with [`crate::signature`].
+pub(crate) struct Parser<'a> {
+    pub(crate) raw: &'a str,
+    pub(crate) offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(raw: &'a str) -> Self {
+        Self { raw, offset: 0 }
+    }
+
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.raw[self.offset..].chars().next()
+    }
+
+    pub(crate) fn error(&self, reason: impl Into<String>) -> DescriptorError {
+        DescriptorError::new(self.offset, reason)
+    }
+
+    pub(crate) fn expect(&mut self, expected: char) -> Result<(), DescriptorError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.offset += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected '{expected}', found '{c}'"))),
+            None => Err(self.error(format!("expected '{expected}', found end of input"))),
+        }
+    }
+
+    pub(crate) fn finish(&self) -> Result<(), DescriptorError> {
+        if self.offset == self.raw.len() {
+            Ok(())
+        } else {
+            Err(self.error("unexpected trailing characters"))
+        }
+    }
+
+    fn field_type(&mut self) -> Result<FieldType, DescriptorError> {
+        let start = self.offset;
+        let mut dimensions = 0;
+        while self.peek() == Some('[') {
+            self.offset += 1;
+            dimensions += 1;
+        }
+
+        if dimensions > MAX_ARRAY_DIMENSIONS {
+            return Err(DescriptorError::new(
+                start,
+                format!("array has more than {MAX_ARRAY_DIMENSIONS} dimensions"),
+            ));
+        }
+
+        let base_type = match self.peek() {
+            Some('L') => None,
+            Some(c) => Some(
+                BaseType::from_descriptor(c)
+                    .ok_or_else(|| self.error(format!("unknown field type '{c}'")))?,
+            ),
+            None => return Err(self.error("expected field type, found end of input")),
+        };
+
+        let field_type = match base_type {
+            Some(base_type) => {
+                self.offset += 1;
+                FieldType::Base(base_type)
+            }
+            None => {
+                self.offset += 1;
+                let class_name_start = self.offset;
+                let length = self.raw[class_name_start..]
+                    .find(';')
+                    .ok_or_else(|| self.error("missing ';' after class name"))?;
+                let class_name = &self.raw[class_name_start..class_name_start + length];
+                if class_name.contains(['.', '[']) {
+                    return Err(DescriptorError::new(
+                        class_name_start,
+                        format!("invalid class name '{class_name}'"),
+                    ));
+                }
+
+                let class_identifier = ClassIdentifier::parse(class_name).map_err(|err| {
+                    DescriptorError::new(class_name_start + err.offset, err.reason)
+                })?;
+                self.offset += length + 1;
+
+                FieldType::Object { class_identifier }
+            }
+        };
+
+        if dimensions == 0 {
+            Ok(field_type)
+        } else {
+            // the element type is never an array here, so the dimensions can't overflow
+            FieldType::array(field_type, dimensions as u8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_method_descriptors() {
+        let descriptor =
+            MethodDescriptor::new("(IDLjava/lang/Thread;[[J)Ljava/lang/Object;").unwrap();
+        assert_eq!(descriptor.parameters.len(), 4);
+        assert_eq!(descriptor.parameters[0], FieldType::Base(BaseType::Int));
+        assert_eq!(descriptor.parameters[1], FieldType::Base(BaseType::Double));
+        assert_eq!(
+            descriptor.parameters[2],
+            FieldType::Object {
+                class_identifier: ClassIdentifier::parse("java.lang.Thread").unwrap()
+            }
+        );
+        assert_eq!(
+            descriptor.parameters[3],
+            FieldType::Array {
+                element_type: Box::new(FieldType::Base(BaseType::Long)),
+                dimensions: 2
+            }
+        );
+        assert_eq!(
+            descriptor.return_descriptor,
+            ReturnDescriptor::FieldType(FieldType::Object {
+                class_identifier: ClassIdentifier::parse("java.lang.Object").unwrap()
+            })
+        );
+
+        let descriptor = MethodDescriptor::new("()V").unwrap();
+        assert!(descriptor.parameters.is_empty());
+        assert_eq!(descriptor.return_descriptor, ReturnDescriptor::Void);
+    }
+
+    #[test]
+    fn parses_field_descriptors() {
+        for (raw, base_type) in [
+            ("B", BaseType::Byte),
+            ("C", BaseType::Char),
+            ("D", BaseType::Double),
+            ("F", BaseType::Float),
+            ("I", BaseType::Int),
+            ("J", BaseType::Long),
+            ("S", BaseType::Short),
+            ("Z", BaseType::Boolean),
+        ] {
+            assert_eq!(FieldType::new(raw).unwrap(), FieldType::Base(base_type));
+        }
+
+        assert!(FieldType::new(&format!("{}I", "[".repeat(255))).is_ok());
+    }
+
+    #[test]
+    fn displays_arrays_with_dimensions() {
+        let field_type = FieldType::new("[[I").unwrap();
+        assert_eq!(field_type.to_string(), "int[][]");
+        assert_eq!(field_type.to_short_string(), "int[][]");
+
+        let field_type = FieldType::new("[Ljava/lang/String;").unwrap();
+        assert_eq!(field_type.to_string(), "java.lang.String[]");
+        assert_eq!(field_type.to_short_string(), "String[]");
+
+        let descriptor = MethodDescriptor::new("(I[I[[Ljava/util/List;)V").unwrap();
+        assert_eq!(descriptor.to_short_string(), "(int, int[], List[][])");
+        assert_eq!(descriptor.to_string(), "(int, int[], java.util.List[][])");
+    }
+
+    #[test]
+    fn nests_arrays() {
+        let array = FieldType::array(FieldType::Base(BaseType::Int), 1).unwrap();
+        assert_eq!(
+            FieldType::array(array.clone(), 2).unwrap(),
+            FieldType::new("[[[I").unwrap()
+        );
+        assert!(FieldType::array(array, 255).is_err());
+    }
+
+    #[test]
+    fn round_trips_descriptors() {
+        for raw in [
+            "()V",
+            "(BCDFIJSZ)V",
+            "([B[[C)[[[D",
+            "(Ljava/lang/String;I)Ljava/lang/Object;",
+            "([Ljava/lang/String;)V",
+            "(LMain;LLogger;LI;)LL;",
+            "(Ljava/util/Map$Entry;)Lcom/acme/Outer$1;",
+        ] {
+            assert_eq!(MethodDescriptor::new(raw).unwrap().to_descriptor(), raw);
+        }
+
+        for raw in [
+            "I",
+            "[J",
+            "[[Z",
+            "Ljava/lang/Integer;",
+            "[Ljava/lang/Integer;",
+        ] {
+            assert_eq!(FieldType::new(raw).unwrap().to_descriptor(), raw);
+        }
+    }
+
+    #[test]
+    fn separates_primitives_from_boxed_classes() {
+        for base_type in BaseType::ALL {
+            let field_type = FieldType::new(&base_type.to_descriptor().to_string()).unwrap();
+            assert_eq!(field_type, FieldType::Base(base_type));
+            assert!(field_type.is_primitive());
+
+            let boxed = base_type.boxed();
+            assert_eq!(BaseType::unboxed(&boxed), Some(base_type));
+
+            let field_type = FieldType::new(&format!("L{};", boxed.to_internal_name())).unwrap();
+            assert!(!field_type.is_primitive());
+            assert_eq!(
+                field_type,
+                FieldType::Object {
+                    class_identifier: boxed
+                }
+            );
+        }
+
+        assert_eq!(BaseType::Int.boxed().to_string(), "java.lang.Integer");
+        assert_eq!(BaseType::Char.boxed().to_string(), "java.lang.Character");
+        assert_eq!(
+            BaseType::unboxed(&ClassIdentifier::parse("java.lang.String").unwrap()),
+            None
+        );
+        assert_eq!(
+            BaseType::unboxed(&ClassIdentifier::parse("com.acme.Integer").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn displays_return_descriptors() {
+        assert_eq!(ReturnDescriptor::Void.to_string(), "void");
+        assert_eq!(
+            MethodDescriptor::new("()[J")
+                .unwrap()
+                .return_descriptor
+                .to_string(),
+            "long[]"
+        );
+    }
+
+    fn error(result: Result<impl std::fmt::Debug, DescriptorError>) -> (usize, String) {
+        let error = result.unwrap_err();
+        (error.offset, error.reason)
+    }
+
+    #[test]
+    fn reports_invalid_descriptors() {
+        assert_eq!(error(MethodDescriptor::new("")).0, 0);
+        assert_eq!(error(MethodDescriptor::new("I)V")).0, 0);
+        assert_eq!(error(MethodDescriptor::new("(I")).0, 2);
+        assert_eq!(error(MethodDescriptor::new("(IX)V")).0, 2);
+        assert_eq!(error(MethodDescriptor::new("(I)")).0, 3);
+        assert_eq!(error(MethodDescriptor::new("(I)VV")).0, 4);
+        assert_eq!(error(MethodDescriptor::new("(V)V")).0, 1);
+        assert_eq!(error(MethodDescriptor::new("(Ljava/lang/String)V")).0, 2);
+        assert_eq!(error(MethodDescriptor::new("(L;)V")).0, 2);
+        assert_eq!(error(MethodDescriptor::new("(Ljava//String;)V")).0, 7);
+        assert_eq!(error(MethodDescriptor::new("(Ljava.lang.String;)V")).0, 2);
+
+        assert_eq!(error(FieldType::new("")).0, 0);
+        assert_eq!(error(FieldType::new("[")).0, 1);
+        assert_eq!(error(FieldType::new("II")).0, 1);
+        assert_eq!(error(FieldType::new(&format!("{}I", "[".repeat(256)))).0, 0);
+    }
 }
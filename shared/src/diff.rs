@@ -0,0 +1,227 @@
+//! Comparison of two runs, e.g. of the same config against two builds of a jar.
+//!
+//! Hidden classes like lambdas get a new name suffix with every run of the JVM, so classes,
+//! methods and call paths are compared without it.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    ClassLoadEvent, MethodEvent,
+    call_tree::{CallNode, CallTree},
+    stats::{MethodKey, MethodStats},
+};
+
+/// The events of one run.
+#[derive(Debug, Clone, Copy)]
+pub struct Run<'a> {
+    pub class_load_events: &'a [ClassLoadEvent],
+    pub method_events: &'a [MethodEvent],
+}
+
+/// Invocations of one method in both runs, `None` if it never returned in that run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodDiff {
+    pub key: MethodKey,
+    pub before: Option<MethodStats>,
+    pub after: Option<MethodStats>,
+}
+
+impl MethodDiff {
+    pub fn calls_before(&self) -> usize {
+        self.before.as_ref().map_or(0, MethodStats::calls)
+    }
+
+    pub fn calls_after(&self) -> usize {
+        self.after.as_ref().map_or(0, MethodStats::calls)
+    }
+
+    /// Change of the total time in microseconds.
+    pub fn total_change(&self) -> i64 {
+        self.after.as_ref().map_or(0, |s| s.total) - self.before.as_ref().map_or(0, |s| s.total)
+    }
+
+    /// Change of the mean latency relative to the first run, `None` unless both runs have
+    /// invocations.
+    pub fn mean_change(&self) -> Option<f64> {
+        match (&self.before, &self.after) {
+            (Some(before), Some(after)) if before.mean > 0 => {
+                Some((after.mean - before.mean) as f64 / before.mean as f64)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunDiff {
+    /// Methods of both runs, largest change of total time first.
+    pub methods: Vec<MethodDiff>,
+    pub classes_only_before: Vec<String>,
+    pub classes_only_after: Vec<String>,
+    /// Call paths as `;` separated frames from the outermost invocation, like folded stacks.
+    pub paths_only_before: Vec<String>,
+    pub paths_only_after: Vec<String>,
+}
+
+impl RunDiff {
+    pub fn new(before: Run, after: Run) -> Self {
+        let before_call_tree = CallTree::build(before.method_events);
+        let after_call_tree = CallTree::build(after.method_events);
+
+        let mut before_stats: HashMap<MethodKey, MethodStats> =
+            MethodStats::collect_by(&before_call_tree, before.method_events, method_key)
+                .into_iter()
+                .map(|stats| (stats.key.clone(), stats))
+                .collect();
+
+        let mut methods: Vec<MethodDiff> =
+            MethodStats::collect_by(&after_call_tree, after.method_events, method_key)
+                .into_iter()
+                .map(|stats| MethodDiff {
+                    key: stats.key.clone(),
+                    before: before_stats.remove(&stats.key),
+                    after: Some(stats),
+                })
+                .collect();
+        methods.extend(before_stats.into_values().map(|stats| MethodDiff {
+            key: stats.key.clone(),
+            before: Some(stats),
+            after: None,
+        }));
+        methods.sort_by(|a, b| {
+            b.total_change()
+                .abs()
+                .cmp(&a.total_change().abs())
+                .then_with(|| a.key.to_string().cmp(&b.key.to_string()))
+        });
+
+        let before_classes = classes(before.class_load_events);
+        let after_classes = classes(after.class_load_events);
+        let before_paths = call_paths(&before_call_tree, before.method_events);
+        let after_paths = call_paths(&after_call_tree, after.method_events);
+
+        Self {
+            methods,
+            classes_only_before: before_classes.difference(&after_classes).cloned().collect(),
+            classes_only_after: after_classes.difference(&before_classes).cloned().collect(),
+            paths_only_before: before_paths.difference(&after_paths).cloned().collect(),
+            paths_only_after: after_paths.difference(&before_paths).cloned().collect(),
+        }
+    }
+}
+
+fn method_key(method_event: &MethodEvent) -> MethodKey {
+    MethodKey {
+        class_identifier: method_event.class_identifier.without_hidden_suffix(),
+        name: method_event.name.clone(),
+        descriptor: method_event.descriptor.clone(),
+    }
+}
+
+fn classes(class_load_events: &[ClassLoadEvent]) -> BTreeSet<String> {
+    class_load_events
+        .iter()
+        .map(|e| e.class_identifier.without_hidden_suffix().to_string())
+        .collect()
+}
+
+fn call_paths(call_tree: &CallTree, method_events: &[MethodEvent]) -> BTreeSet<String> {
+    fn collect(
+        paths: &mut BTreeSet<String>,
+        parent: &str,
+        node: &CallNode,
+        method_events: &[MethodEvent],
+    ) {
+        let frame = method_key(&method_events[node.entry]).to_string();
+        let path = if parent.is_empty() {
+            frame
+        } else {
+            format!("{};{}", parent, frame)
+        };
+
+        for child in &node.children {
+            collect(paths, &path, child, method_events);
+        }
+        paths.insert(path);
+    }
+
+    let mut paths = BTreeSet::new();
+    for thread_call_tree in &call_tree.threads {
+        for node in &thread_call_tree.roots {
+            collect(&mut paths, "", node, method_events);
+        }
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MethodEventType,
+        test_support::{class_load_event, method_event},
+    };
+
+    #[test]
+    fn compares_runs() {
+        let service = "com.acme.Service";
+        let lambda = "com.acme.Main$$Lambda/0x0000000800c01200";
+        let before_methods = vec![
+            method_event(0, "handle", MethodEventType::Entry).with_class(service),
+            method_event(10, "validate", MethodEventType::Entry).with_class(service),
+            method_event(20, "validate", MethodEventType::Exit).with_class(service),
+            method_event(100, "handle", MethodEventType::Exit).with_class(service),
+            method_event(200, "run", MethodEventType::Entry).with_class(lambda),
+            method_event(210, "run", MethodEventType::Exit).with_class(lambda),
+        ];
+        let before_classes = vec![class_load_event(service), class_load_event(lambda)];
+
+        let lambda = "com.acme.Main$$Lambda/0x0000000800c0a000";
+        let after_methods = vec![
+            method_event(0, "handle", MethodEventType::Entry).with_class(service),
+            method_event(10, "cache", MethodEventType::Entry).with_class(service),
+            method_event(15, "cache", MethodEventType::Exit).with_class(service),
+            method_event(300, "handle", MethodEventType::Exit).with_class(service),
+            method_event(400, "run", MethodEventType::Entry).with_class(lambda),
+            method_event(410, "run", MethodEventType::Exit).with_class(lambda),
+        ];
+        let after_classes = vec![
+            class_load_event(service),
+            class_load_event(lambda),
+            class_load_event("com.acme.Cache"),
+        ];
+
+        let diff = RunDiff::new(
+            Run {
+                class_load_events: &before_classes,
+                method_events: &before_methods,
+            },
+            Run {
+                class_load_events: &after_classes,
+                method_events: &after_methods,
+            },
+        );
+
+        let names: Vec<&str> = diff.methods.iter().map(|m| m.key.name.as_str()).collect();
+        assert_eq!(names, ["handle", "validate", "cache", "run"]);
+
+        let handle = &diff.methods[0];
+        assert_eq!(handle.total_change(), 200);
+        assert_eq!(handle.mean_change(), Some(2.0));
+        assert_eq!((handle.calls_before(), handle.calls_after()), (1, 1));
+        assert_eq!(diff.methods[1].after, None);
+        assert_eq!(diff.methods[3].total_change(), 0);
+
+        assert!(diff.classes_only_before.is_empty());
+        assert_eq!(diff.classes_only_after, ["com.acme.Cache"]);
+        assert_eq!(
+            diff.paths_only_before,
+            ["com.acme.Service.handle();com.acme.Service.validate()"]
+        );
+        assert_eq!(
+            diff.paths_only_after,
+            ["com.acme.Service.handle();com.acme.Service.cache()"]
+        );
+    }
+}
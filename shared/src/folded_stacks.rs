@@ -0,0 +1,135 @@
+//! Export to the collapsed stack format read by inferno and flamegraph.pl, one line per distinct
+//! stack with frames joined by `;` followed by its weight.
+
+use std::{collections::BTreeMap, fmt::Display, io::Write};
+
+use crate::{
+    MethodEvent,
+    call_tree::{CallNode, CallTree},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weight {
+    /// Number of invocations.
+    Calls,
+    /// Exclusive time in microseconds, invocations that never returned are left out.
+    Micros,
+}
+
+impl Display for Weight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Weight::Calls => write!(f, "calls"),
+            Weight::Micros => write!(f, "µs"),
+        }
+    }
+}
+
+/// Name of the frame of the invoked method, e.g. `com.acme.Service.handle(int, String)`.
+pub fn frame_name(method_event: &MethodEvent) -> String {
+    format!(
+        "{}.{}{}",
+        method_event.class_identifier,
+        method_event.name,
+        method_event.descriptor.to_short_string()
+    )
+}
+
+/// Sums the weights of all invocations by their stack, stacks of all threads are merged.
+pub fn fold(method_events: &[MethodEvent], weight: Weight) -> BTreeMap<String, i64> {
+    let mut stacks = BTreeMap::new();
+
+    let call_tree = CallTree::build(method_events);
+    for thread_call_tree in &call_tree.threads {
+        for node in &thread_call_tree.roots {
+            fold_node(&mut stacks, "", node, method_events, weight);
+        }
+    }
+
+    stacks.retain(|_, weight| *weight > 0);
+    stacks
+}
+
+fn fold_node(
+    stacks: &mut BTreeMap<String, i64>,
+    parent: &str,
+    node: &CallNode,
+    method_events: &[MethodEvent],
+    weight: Weight,
+) {
+    // frame separators in names would split the frame
+    let frame = frame_name(&method_events[node.entry]).replace(';', ":");
+    let stack = if parent.is_empty() {
+        frame
+    } else {
+        format!("{};{}", parent, frame)
+    };
+
+    let value = match weight {
+        Weight::Calls => 1,
+        Weight::Micros => node.exclusive_time().unwrap_or(0),
+    };
+    *stacks.entry(stack.clone()).or_insert(0) += value;
+
+    for child in &node.children {
+        fold_node(stacks, &stack, child, method_events, weight);
+    }
+}
+
+pub fn write(
+    mut out: impl Write,
+    method_events: &[MethodEvent],
+    weight: Weight,
+) -> std::io::Result<()> {
+    for (stack, value) in fold(method_events, weight) {
+        writeln!(out, "{} {}", stack, value)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MethodEventType, test_support::method_event};
+
+    /// Events of methods with parameters, so that their frames show them.
+    fn events() -> Vec<MethodEvent> {
+        [
+            method_event(0, "outer", MethodEventType::Entry),
+            method_event(10, "inner", MethodEventType::Entry),
+            method_event(40, "inner", MethodEventType::Exit),
+            method_event(50, "inner", MethodEventType::Entry),
+            method_event(60, "inner", MethodEventType::Exit),
+            method_event(100, "outer", MethodEventType::Exit),
+            method_event(0, "outer", MethodEventType::Entry).on_thread(2),
+            method_event(20, "outer", MethodEventType::Exit).on_thread(2),
+        ]
+        .into_iter()
+        .map(|event| event.with_descriptor("(ILjava/lang/String;)V"))
+        .collect()
+    }
+
+    #[test]
+    fn weights_by_calls() {
+        let stacks = fold(&events(), Weight::Calls);
+
+        let outer = "com.acme.Service.outer(int, String)";
+        let inner = format!("{};com.acme.Service.inner(int, String)", outer);
+        assert_eq!(stacks.len(), 2);
+        assert_eq!(stacks[outer], 2);
+        assert_eq!(stacks[&inner], 2);
+    }
+
+    #[test]
+    fn weights_by_exclusive_time() {
+        let mut out = Vec::new();
+        write(&mut out, &events(), Weight::Micros).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "com.acme.Service.outer(int, String) 80\n\
+             com.acme.Service.outer(int, String);com.acme.Service.inner(int, String) 40\n"
+        );
+    }
+}
@@ -1,15 +1,43 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{fmt::Display, path::PathBuf};
 
-use crate::{class::ClassIdentifier, descriptor::MethodDescriptor};
+use crate::{
+    class::ClassIdentifier,
+    descriptor::MethodDescriptor,
+    signature::{ClassSignature, MethodSignature},
+    value::Value,
+};
 
+pub mod allocation;
+pub mod assertion;
+pub mod call_tree;
+pub mod chrome_trace;
 pub mod class;
 pub mod descriptor;
+pub mod diff;
+pub mod folded_stacks;
+pub mod matcher;
+pub mod recording;
+pub mod signature;
+pub mod stats;
+#[cfg(test)]
+mod test_support;
+pub mod value;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ThreadInfo {
+    /// Assigned by the agent, unique for the lifetime of the traced JVM.
+    pub id: u64,
+    pub name: String,
+}
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ClassLoadEvent {
     pub timestamp: i64,
     pub class_identifier: ClassIdentifier,
+    /// Generic signature, only present for classes that declare or extend generic types.
+    pub signature: Option<ClassSignature>,
+    pub thread: ThreadInfo,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -24,42 +52,211 @@ pub struct MethodEvent {
     pub name: String,
     pub class_identifier: ClassIdentifier,
     pub descriptor: MethodDescriptor,
+    /// Generic signature, only present for methods that use generic types.
+    pub signature: Option<MethodSignature>,
     pub method_event_type: MethodEventType,
+    pub thread: ThreadInfo,
+    /// Parameter values, only captured on entry.
+    pub arguments: Vec<Value>,
+    /// Returned value, only captured on exit of non-void methods that returned normally.
+    pub return_value: Option<Value>,
+    /// Whether the frame was popped by a thrown exception instead of a return.
+    pub was_popped_by_exception: bool,
+}
+
+impl MethodEvent {
+    /// Whether both events belong to the same method, regardless of the invocation.
+    pub fn is_same_method(&self, other: &MethodEvent) -> bool {
+        self.name == other.name
+            && self.class_identifier == other.class_identifier
+            && self.descriptor == other.descriptor
+    }
+}
+
+/// A position inside a method body.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MethodLocation {
+    pub class_identifier: ClassIdentifier,
+    pub name: String,
+    pub descriptor: MethodDescriptor,
+    /// Bytecode index, `-1` for native methods.
+    pub location: i64,
+    pub line_number: Option<i32>,
+}
+
+impl Display for MethodLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}{}",
+            self.class_identifier.short_name(),
+            self.name,
+            self.descriptor.to_short_string()
+        )?;
+
+        match self.line_number {
+            Some(line_number) => write!(f, ":{}", line_number),
+            None => write!(f, "@{}", self.location),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum ExceptionEventType {
+    Thrown,
+    Caught,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ExceptionEvent {
+    pub timestamp: i64,
+    pub exception_event_type: ExceptionEventType,
+    pub class_identifier: ClassIdentifier,
+    pub message: Option<String>,
+    /// Where the exception was thrown, only known for thrown events.
+    pub throw_location: Option<MethodLocation>,
+    /// Where the exception is or will be caught, `None` for uncaught exceptions.
+    pub catch_location: Option<MethodLocation>,
+    pub thread: ThreadInfo,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum AllocationEventType {
+    /// One of the allocations JVMTI samples about every sampling interval bytes.
+    Sampled,
+    /// An allocation by the VM itself, e.g. through reflection or JNI, which is always reported.
+    VirtualMachine,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct AllocationEvent {
+    pub timestamp: i64,
+    pub allocation_event_type: AllocationEventType,
+    pub class_identifier: ClassIdentifier,
+    /// Size of the allocated object in bytes.
+    pub size: i64,
+    pub thread: ThreadInfo,
+    /// Innermost frame first, bounded by [`AllocationConfig::max_frames`].
+    pub stack_trace: Vec<MethodLocation>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub enum AgentMessage {
     Unload,
-    ClassLoad(ClassLoadEvent),
-    MethodEvent(MethodEvent),
+    ClassLoad(Box<ClassLoadEvent>),
+    MethodEvent(Box<MethodEvent>),
+    Exception(Box<ExceptionEvent>),
+    Allocation(Box<AllocationEvent>),
 }
 
-#[derive(Deserialize, Debug)]
+impl AgentMessage {
+    /// When the event happened in microseconds since the epoch, `None` for [`AgentMessage::Unload`].
+    pub fn timestamp(&self) -> Option<i64> {
+        match self {
+            AgentMessage::Unload => None,
+            AgentMessage::ClassLoad(event) => Some(event.timestamp),
+            AgentMessage::MethodEvent(event) => Some(event.timestamp),
+            AgentMessage::Exception(event) => Some(event.timestamp),
+            AgentMessage::Allocation(event) => Some(event.timestamp),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct MethodConfig {
     pub name: String,
     pub class: String,
+    /// Restricts the match to one overload, see [`matcher::DescriptorPattern`].
+    pub descriptor: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Config {
     pub jar: String,
     pub class_loads: Vec<String>,
+    #[serde(default)]
+    pub exclude_class_loads: Vec<String>,
+    /// Exception classes whose throws and catches are traced.
+    #[serde(default)]
+    pub exceptions: Vec<String>,
     pub methods: Vec<MethodConfig>,
+    #[serde(default)]
+    pub exclude_methods: Vec<MethodConfig>,
+    /// Call `toString()` on object arguments instead of only reporting their identity hash.
+    #[serde(default)]
+    pub call_to_string: bool,
+    /// Checked after headless runs, see [`assertion`]. Their targets are traced even if the
+    /// filters above leave them out, headless runs then check those events without writing them.
+    #[serde(default)]
+    pub assertions: Vec<AssertionConfig>,
+    /// Allocations are only traced if this is set.
+    pub allocations: Option<AllocationConfig>,
 }
 
-impl Config {
-    pub fn includes_method(&self, name: &str, class: &str) -> bool {
-        for method in &self.methods {
-            if method.name == name && method.class == class {
-                return true;
-            }
-        }
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AllocationConfig {
+    /// Average number of bytes allocated between two sampled allocations.
+    #[serde(default = "default_sampling_interval")]
+    pub sampling_interval: i32,
+    /// Number of frames captured with each allocation.
+    #[serde(default = "default_max_frames")]
+    pub max_frames: usize,
+}
+
+fn default_sampling_interval() -> i32 {
+    // same as the JVM's default
+    512 * 1024
+}
 
-        false
+fn default_max_frames() -> usize {
+    16
+}
+
+/// A condition on a run, either on a method when `method` is set or on a class otherwise.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AssertionConfig {
+    pub class: String,
+    pub method: Option<String>,
+    pub descriptor: Option<String>,
+    pub max_calls: Option<usize>,
+    pub min_calls: Option<usize>,
+    /// Duration like `20ms`, `1.5s` or `500us`.
+    pub max_latency: Option<String>,
+    /// Percentile `max_latency` applies to, every invocation when unset.
+    pub percentile: Option<f64>,
+    #[serde(default)]
+    pub never_load: bool,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "{}", err),
+            ConfigError::Parse(err) => write!(f, "invalid config: {}", err),
+        }
     }
 }
 
-pub fn load_config(path: PathBuf) -> Config {
-    let config_str = std::fs::read_to_string(path).unwrap();
-    toml::from_str(&config_str).unwrap()
+impl std::error::Error for ConfigError {}
+
+pub fn load_config(path: PathBuf) -> Result<Config, ConfigError> {
+    let config_str = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+    toml::from_str(&config_str).map_err(ConfigError::Parse)
+}
+
+/// Formats a duration given in microseconds with a unit that fits its magnitude.
+pub fn format_duration(micros: i64) -> String {
+    if micros.abs() >= 1_000_000 {
+        format!("{:.2} s", micros as f64 / 1_000_000.0)
+    } else if micros.abs() >= 1_000 {
+        format!("{:.2} ms", micros as f64 / 1_000.0)
+    } else {
+        format!("{} µs", micros)
+    }
 }
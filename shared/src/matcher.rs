@@ -0,0 +1,586 @@
+use std::fmt::Display;
+
+use regex::Regex;
+
+use crate::{
+    Config, MethodConfig,
+    descriptor::{FieldType, MethodDescriptor},
+};
+
+/// A single name pattern from the config.
+///
+/// - `regex:<expression>` is a regular expression that has to match the whole name
+/// - `**` matches any sequence of characters, including `.`
+/// - `*` matches any sequence of characters except `.`
+/// - `?` matches a single character except `.`
+/// - anything else has to match exactly
+#[derive(Debug)]
+pub enum Pattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+#[derive(Debug)]
+pub struct PatternError {
+    pub pattern: String,
+    pub reason: String,
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid pattern '{}': {}", self.pattern, self.reason)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl Pattern {
+    pub fn parse(raw: &str) -> Result<Self, PatternError> {
+        let expression = if let Some(expression) = raw.strip_prefix("regex:") {
+            format!("^(?:{})$", expression)
+        } else if raw.contains(['*', '?']) {
+            glob_to_regex(raw)
+        } else {
+            return Ok(Self::Exact(raw.to_string()));
+        };
+
+        Regex::new(&expression)
+            .map(Self::Regex)
+            .map_err(|err| PatternError {
+                pattern: raw.to_string(),
+                reason: err.to_string(),
+            })
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Pattern::Exact(exact) => exact == name,
+            Pattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut expression = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                expression.push_str(".*");
+            }
+            '*' => expression.push_str("[^.]*"),
+            '?' => expression.push_str("[^.]"),
+            c => expression.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    expression.push('$');
+    expression
+}
+
+/// Patterns that match if any include matches and no exclude does.
+#[derive(Debug, Default)]
+pub struct PatternList {
+    includes: Vec<Pattern>,
+    excludes: Vec<Pattern>,
+}
+
+impl PatternList {
+    pub fn new(includes: &[String], excludes: &[String]) -> Result<Self, PatternError> {
+        Ok(Self {
+            includes: parse_all(includes)?,
+            excludes: parse_all(excludes)?,
+        })
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.includes.iter().any(|p| p.matches(name))
+            && !self.excludes.iter().any(|p| p.matches(name))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.includes.is_empty()
+    }
+}
+
+fn parse_all(raw: &[String]) -> Result<Vec<Pattern>, PatternError> {
+    raw.iter().map(|r| Pattern::parse(r)).collect()
+}
+
+/// Selects overloads by their descriptor.
+///
+/// Accepts either the JVM form, e.g. `(I)Ljava/lang/String;`, which has to match exactly, or
+/// a Java source like parameter list, e.g. `(int, String[])`, which only has to match the
+/// parameters. Classes in the parameter list may be given with or without their package.
+/// Parameters that are valid in JVM form, e.g. `(I)`, are taken as JVM form and need a return
+/// type.
+#[derive(Debug)]
+pub enum DescriptorPattern {
+    Exact(MethodDescriptor),
+    Parameters(Vec<ParameterPattern>),
+}
+
+#[derive(Debug)]
+pub struct ParameterPattern {
+    type_name: String,
+    dimensions: usize,
+}
+
+impl DescriptorPattern {
+    pub fn parse(raw: &str) -> Result<Self, PatternError> {
+        let error = |reason: &str| PatternError {
+            pattern: raw.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let raw = raw.trim();
+        let parameters = raw
+            .strip_prefix('(')
+            .ok_or_else(|| error("descriptor has to start with '('"))?;
+        let (parameters, return_type) = parameters
+            .split_once(')')
+            .ok_or_else(|| error("missing ')'"))?;
+
+        if !return_type.is_empty() {
+            return MethodDescriptor::new(raw)
+                .map(Self::Exact)
+                .map_err(|err| error(&err.to_string()));
+        }
+
+        if parameters.trim().is_empty() {
+            return Ok(Self::Parameters(Vec::new()));
+        }
+
+        // parameters in JVM form would be taken for a parameter list that never matches
+        if MethodDescriptor::new(&format!("({})V", parameters)).is_ok() {
+            return Err(error("JVM descriptor is missing its return type"));
+        }
+
+        parameters
+            .split(',')
+            .map(|parameter| {
+                let mut type_name = parameter.trim();
+                let mut dimensions = 0;
+                while let Some(element_type) = type_name.strip_suffix("[]") {
+                    type_name = element_type.trim_end();
+                    dimensions += 1;
+                }
+
+                if type_name.is_empty() {
+                    return Err(error("empty parameter type"));
+                }
+
+                Ok(ParameterPattern {
+                    type_name: type_name.to_string(),
+                    dimensions,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Self::Parameters)
+    }
+
+    pub fn matches(&self, descriptor: &MethodDescriptor) -> bool {
+        match self {
+            DescriptorPattern::Exact(exact) => exact == descriptor,
+            DescriptorPattern::Parameters(parameters) => {
+                parameters.len() == descriptor.parameters.len()
+                    && parameters
+                        .iter()
+                        .zip(&descriptor.parameters)
+                        .all(|(pattern, parameter)| pattern.matches(parameter))
+            }
+        }
+    }
+}
+
+impl ParameterPattern {
+    fn matches(&self, field_type: &FieldType) -> bool {
+        let (element_type, dimensions) = match field_type {
+            FieldType::Array {
+                element_type,
+                dimensions,
+            } => (element_type.as_ref(), *dimensions as usize),
+            _ => (field_type, 0),
+        };
+
+        if dimensions != self.dimensions {
+            return false;
+        }
+
+        match element_type {
+            FieldType::Base(base_type) => base_type.to_string() == self.type_name,
+            FieldType::Object { class_identifier } => {
+                if self.type_name.contains('.') {
+                    class_identifier.to_string() == self.type_name
+                } else {
+                    class_identifier.name() == self.type_name
+                        || class_identifier.short_name() == self.type_name
+                }
+            }
+            FieldType::Array { .. } => unreachable!("array element types are never arrays"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MethodPattern {
+    name: Pattern,
+    class: Pattern,
+    descriptor: Option<DescriptorPattern>,
+}
+
+impl MethodPattern {
+    pub fn new(method_config: &MethodConfig) -> Result<Self, PatternError> {
+        Ok(Self {
+            name: Pattern::parse(&method_config.name)?,
+            class: Pattern::parse(&method_config.class)?,
+            descriptor: method_config
+                .descriptor
+                .as_deref()
+                .map(DescriptorPattern::parse)
+                .transpose()?,
+        })
+    }
+
+    /// Whether name and class match, the descriptor is not checked.
+    pub fn matches_name(&self, name: &str, class: &str) -> bool {
+        self.name.matches(name) && self.class.matches(class)
+    }
+
+    pub fn matches(&self, name: &str, class: &str, descriptor: &MethodDescriptor) -> bool {
+        self.matches_name(name, class)
+            && self
+                .descriptor
+                .as_ref()
+                .is_none_or(|pattern| pattern.matches(descriptor))
+    }
+}
+
+/// Compiled form of the filters in [`Config`], built once when the agent is loaded.
+#[derive(Debug)]
+pub struct Matcher {
+    class_loads: PatternList,
+    exceptions: PatternList,
+    methods: Vec<MethodPattern>,
+    exclude_methods: Vec<MethodPattern>,
+    /// Targets of the assertions, traced regardless of the filters so they can be checked.
+    assertion_classes: Vec<Pattern>,
+    assertion_methods: Vec<MethodPattern>,
+}
+
+impl Matcher {
+    pub fn new(config: &Config) -> Result<Self, PatternError> {
+        let mut assertion_classes = Vec::new();
+        let mut assertion_methods = Vec::new();
+        for assertion in &config.assertions {
+            match &assertion.method {
+                Some(method) => assertion_methods.push(MethodPattern::new(&MethodConfig {
+                    name: method.clone(),
+                    class: assertion.class.clone(),
+                    descriptor: assertion.descriptor.clone(),
+                })?),
+                None => assertion_classes.push(Pattern::parse(&assertion.class)?),
+            }
+        }
+
+        Ok(Self {
+            class_loads: PatternList::new(&config.class_loads, &config.exclude_class_loads)?,
+            exceptions: PatternList::new(&config.exceptions, &[])?,
+            methods: config
+                .methods
+                .iter()
+                .map(MethodPattern::new)
+                .collect::<Result<_, _>>()?,
+            exclude_methods: config
+                .exclude_methods
+                .iter()
+                .map(MethodPattern::new)
+                .collect::<Result<_, _>>()?,
+            assertion_classes,
+            assertion_methods,
+        })
+    }
+
+    pub fn includes_class_load(&self, class: &str) -> bool {
+        self.filters_class_load(class) || self.assertion_classes.iter().any(|p| p.matches(class))
+    }
+
+    /// Whether the filters include the class load, leaving out assertion targets.
+    pub fn filters_class_load(&self, class: &str) -> bool {
+        self.class_loads.matches(class)
+    }
+
+    pub fn includes_exception(&self, class: &str) -> bool {
+        self.exceptions.matches(class)
+    }
+
+    pub fn traces_exceptions(&self) -> bool {
+        !self.exceptions.is_empty()
+    }
+
+    /// Whether some overload of the method could be included, to filter methods before their
+    /// descriptor is parsed.
+    pub fn may_include_method(&self, name: &str, class: &str) -> bool {
+        self.methods
+            .iter()
+            .chain(&self.assertion_methods)
+            .any(|m| m.matches_name(name, class))
+    }
+
+    pub fn includes_method(&self, name: &str, class: &str, descriptor: &MethodDescriptor) -> bool {
+        self.filters_method(name, class, descriptor)
+            || self
+                .assertion_methods
+                .iter()
+                .any(|m| m.matches(name, class, descriptor))
+    }
+
+    /// Whether the filters include the method, leaving out assertion targets.
+    pub fn filters_method(&self, name: &str, class: &str, descriptor: &MethodDescriptor) -> bool {
+        self.methods
+            .iter()
+            .any(|m| m.matches(name, class, descriptor))
+            && !self
+                .exclude_methods
+                .iter()
+                .any(|m| m.matches(name, class, descriptor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        Pattern::parse(pattern).unwrap().matches(name)
+    }
+
+    #[test]
+    fn exact() {
+        assert!(matches("java.lang.String", "java.lang.String"));
+        assert!(!matches("java.lang.String", "java.lang.StringBuilder"));
+        assert!(!matches("java.lang", "java.lang.String"));
+    }
+
+    #[test]
+    fn single_star_stays_in_package() {
+        assert!(matches("com.acme.*", "com.acme.Service"));
+        assert!(!matches("com.acme.*", "com.acme.impl.Service"));
+        assert!(matches("com.acme.*Service", "com.acme.UserService"));
+        assert!(matches("*", "<init>"));
+        assert!(matches("get*", "getName"));
+        assert!(!matches("get*", "setName"));
+    }
+
+    #[test]
+    fn double_star_crosses_packages() {
+        assert!(matches("com.acme.**", "com.acme.Service"));
+        assert!(matches("com.acme.**", "com.acme.impl.deep.Service"));
+        assert!(!matches("com.acme.**", "com.acmex.Service"));
+        assert!(matches("**.Service", "com.acme.Service"));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(matches("get?", "getX"));
+        assert!(!matches("get?", "getXY"));
+        assert!(!matches("a?b", "a.b"));
+    }
+
+    #[test]
+    fn glob_escapes_regex_characters() {
+        assert!(matches("Outer$*", "Outer$Inner"));
+        assert!(!matches("java.lang.*", "javaXlang.String"));
+    }
+
+    #[test]
+    fn regex() {
+        assert!(matches("regex:get.*", "getName"));
+        assert!(!matches("regex:get.*", "forget"));
+        assert!(matches("regex:(get|set)Name", "setName"));
+        assert!(matches("regex:com\\.acme\\..*", "com.acme.impl.Service"));
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let err = Pattern::parse("regex:get(").unwrap_err();
+        assert_eq!(err.pattern, "regex:get(");
+    }
+
+    #[test]
+    fn excludes() {
+        let list = PatternList::new(
+            &["java.lang.**".to_string()],
+            &["java.lang.invoke.**".to_string()],
+        )
+        .unwrap();
+
+        assert!(list.matches("java.lang.String"));
+        assert!(!list.matches("java.lang.invoke.MethodHandle"));
+        assert!(!list.matches("java.util.List"));
+    }
+
+    #[test]
+    fn methods() {
+        let config: Config = toml::from_str(
+            r#"
+            jar = 'app.jar'
+            class_loads = []
+
+            [[methods]]
+            name = '*'
+            class = 'com.acme.**'
+
+            [[methods]]
+            name = 'regex:get.*'
+            class = 'java.util.HashMap'
+
+            [[exclude_methods]]
+            name = '<init>'
+            class = '**'
+            "#,
+        )
+        .unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        let descriptor = MethodDescriptor::new("()V").unwrap();
+
+        assert!(matcher.includes_method("handle", "com.acme.Service", &descriptor));
+        assert!(matcher.includes_method("handle", "com.acme.impl.Service", &descriptor));
+        assert!(!matcher.includes_method("<init>", "com.acme.Service", &descriptor));
+        assert!(matcher.includes_method("getOrDefault", "java.util.HashMap", &descriptor));
+        assert!(!matcher.includes_method("put", "java.util.HashMap", &descriptor));
+        assert!(!matcher.includes_method("get", "java.util.TreeMap", &descriptor));
+        assert!(matcher.may_include_method("put", "com.acme.Service"));
+        assert!(!matcher.may_include_method("put", "java.util.HashMap"));
+    }
+
+    #[test]
+    fn assertion_targets() {
+        let config: Config = toml::from_str(
+            r#"
+            jar = 'app.jar'
+            class_loads = ['com.acme.**']
+            exclude_class_loads = ['com.acme.Legacy*']
+
+            [[methods]]
+            name = 'handle'
+            class = 'com.acme.Service'
+
+            [[assertions]]
+            class = 'java.lang.String'
+            method = 'concat'
+            max_calls = 500
+
+            [[assertions]]
+            class = 'com.acme.LegacyCache'
+            never_load = true
+            "#,
+        )
+        .unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        let descriptor = MethodDescriptor::new("()V").unwrap();
+
+        assert!(matcher.includes_method("concat", "java.lang.String", &descriptor));
+        assert!(!matcher.filters_method("concat", "java.lang.String", &descriptor));
+        assert!(!matcher.includes_method("trim", "java.lang.String", &descriptor));
+        assert!(matcher.includes_class_load("com.acme.LegacyCache"));
+        assert!(!matcher.filters_class_load("com.acme.LegacyCache"));
+        assert!(!matcher.includes_class_load("com.acme.LegacyStore"));
+    }
+
+    fn matches_descriptor(pattern: &str, descriptor: &str) -> bool {
+        DescriptorPattern::parse(pattern)
+            .unwrap()
+            .matches(&MethodDescriptor::new(descriptor).unwrap())
+    }
+
+    #[test]
+    fn jvm_descriptor() {
+        assert!(matches_descriptor(
+            "(I)Ljava/lang/String;",
+            "(I)Ljava/lang/String;"
+        ));
+        assert!(!matches_descriptor(
+            "(I)Ljava/lang/String;",
+            "(J)Ljava/lang/String;"
+        ));
+        assert!(!matches_descriptor("(I)V", "(I)Ljava/lang/String;"));
+    }
+
+    #[test]
+    fn java_parameters() {
+        assert!(matches_descriptor("(int)", "(I)Ljava/lang/String;"));
+        assert!(!matches_descriptor("(int)", "(J)Ljava/lang/String;"));
+        assert!(matches_descriptor("()", "()V"));
+        assert!(!matches_descriptor("()", "(I)V"));
+        assert!(matches_descriptor(
+            "(java.lang.Object, String)",
+            "(Ljava/lang/Object;Ljava/lang/String;)V"
+        ));
+        assert!(!matches_descriptor(
+            "(com.acme.String)",
+            "(Ljava/lang/String;)V"
+        ));
+        assert!(matches_descriptor(
+            "(char[], int , int)",
+            "([CII)Ljava/lang/String;"
+        ));
+        assert!(!matches_descriptor("(char)", "([C)Ljava/lang/String;"));
+        assert!(matches_descriptor(
+            "(String[][])",
+            "([[Ljava/lang/String;)V"
+        ));
+    }
+
+    #[test]
+    fn invalid_descriptor() {
+        assert!(DescriptorPattern::parse("int").is_err());
+        assert!(DescriptorPattern::parse("(int").is_err());
+        assert!(DescriptorPattern::parse("(int,)").is_err());
+        assert!(DescriptorPattern::parse("(I)X").is_err());
+    }
+
+    #[test]
+    fn jvm_descriptor_without_return_type() {
+        assert!(DescriptorPattern::parse("(I)").is_err());
+        assert!(DescriptorPattern::parse("([CII)").is_err());
+        assert!(DescriptorPattern::parse("(Ljava/lang/String;)").is_err());
+        assert!(DescriptorPattern::parse("(int, String)").is_ok());
+    }
+
+    #[test]
+    fn single_letter_classes() {
+        assert!(matches_descriptor("(T)", "(Lcom/acme/T;)V"));
+        assert!(!matches_descriptor("(T)", "(I)V"));
+    }
+
+    #[test]
+    fn selects_overload() {
+        let config: Config = toml::from_str(
+            r#"
+            jar = 'app.jar'
+            class_loads = []
+
+            [[methods]]
+            name = 'valueOf'
+            class = 'java.lang.String'
+            descriptor = '(int)'
+            "#,
+        )
+        .unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+
+        assert!(matcher.includes_method(
+            "valueOf",
+            "java.lang.String",
+            &MethodDescriptor::new("(I)Ljava/lang/String;").unwrap()
+        ));
+        assert!(!matcher.includes_method(
+            "valueOf",
+            "java.lang.String",
+            &MethodDescriptor::new("(Ljava/lang/Object;)Ljava/lang/String;").unwrap()
+        ));
+    }
+}
@@ -0,0 +1,437 @@
+//! Binary recording of a trace session.
+//!
+//! Layout, all integers little endian:
+//!
+//! - header: magic `AIDAREC\0`, format version (u32)
+//! - config: length (u32) followed by the serialized [`Config`]
+//! - events: length (u32) followed by the serialized [`AgentMessage`], repeated
+//! - index: number of events (u64) followed by the offset of every event (u64)
+//! - trailer: offset of the index (u64), magic `AIDAIDX\0`
+//!
+//! Index and trailer are only written once a recording is finished. Recordings without them,
+//! e.g. from a crashed session, are indexed by scanning the events instead.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{AgentMessage, Config};
+
+const MAGIC: &[u8; 8] = b"AIDAREC\0";
+const INDEX_MAGIC: &[u8; 8] = b"AIDAIDX\0";
+/// bincode is not self-describing, so this has to be bumped with every change to [`Config`],
+/// [`AgentMessage`] or any type they contain.
+pub const VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(std::io::Error),
+    Serialization(bincode::Error),
+    InvalidFormat(String),
+    /// A recording whose header is intact but whose contents are not.
+    Corrupt(String),
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingError::Io(err) => write!(f, "{}", err),
+            RecordingError::Serialization(err) => write!(f, "malformed event: {}", err),
+            RecordingError::InvalidFormat(reason) => write!(f, "not a recording: {}", reason),
+            RecordingError::Corrupt(reason) => write!(f, "corrupt recording: {}", reason),
+            RecordingError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported recording version {} (expected {})",
+                version, VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(err: std::io::Error) -> Self {
+        RecordingError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for RecordingError {
+    fn from(err: bincode::Error) -> Self {
+        RecordingError::Serialization(err)
+    }
+}
+
+/// Streams events of a session to a recording file.
+pub struct RecordingWriter {
+    writer: BufWriter<File>,
+    position: u64,
+    offsets: Vec<u64>,
+}
+
+impl RecordingWriter {
+    /// Creates the file at `path` and writes the header and `config` to it.
+    pub fn create(path: &Path, config: &Config) -> Result<Self, RecordingError> {
+        let mut recording = Self {
+            writer: BufWriter::new(File::create(path)?),
+            position: 0,
+            offsets: Vec::new(),
+        };
+
+        recording.write_bytes(MAGIC)?;
+        recording.write_bytes(&VERSION.to_le_bytes())?;
+        recording.write_record(&bincode::serialize(config)?)?;
+
+        Ok(recording)
+    }
+
+    pub fn write(&mut self, message: &AgentMessage) -> Result<(), RecordingError> {
+        self.offsets.push(self.position);
+        self.write_record(&bincode::serialize(message)?)
+    }
+
+    /// Writes buffered events to disk, so they survive if the recording is never finished.
+    pub fn flush(&mut self) -> Result<(), RecordingError> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Writes the index and trailer.
+    pub fn finish(mut self) -> Result<(), RecordingError> {
+        let index_offset = self.position;
+
+        self.write_bytes(&(self.offsets.len() as u64).to_le_bytes())?;
+        for offset in std::mem::take(&mut self.offsets) {
+            self.write_bytes(&offset.to_le_bytes())?;
+        }
+
+        self.write_bytes(&index_offset.to_le_bytes())?;
+        self.write_bytes(INDEX_MAGIC)?;
+        self.flush()
+    }
+
+    fn write_record(&mut self, bytes: &[u8]) -> Result<(), RecordingError> {
+        let length = u32::try_from(bytes.len())
+            .map_err(|_| RecordingError::InvalidFormat("event too large".to_string()))?;
+
+        self.write_bytes(&length.to_le_bytes())?;
+        self.write_bytes(bytes)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), RecordingError> {
+        self.writer.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// Random access to the events of a recording file.
+pub struct RecordingReader {
+    reader: BufReader<File>,
+    /// Length of the file in bytes.
+    length: u64,
+    config: Config,
+    offsets: Vec<u64>,
+}
+
+impl RecordingReader {
+    pub fn open(path: &Path) -> Result<Self, RecordingError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let length = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut magic = [0; 8];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| RecordingError::InvalidFormat("file is too short".to_string()))?;
+        if &magic != MAGIC {
+            return Err(RecordingError::InvalidFormat(
+                "missing recording header".to_string(),
+            ));
+        }
+
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(RecordingError::UnsupportedVersion(version));
+        }
+
+        let config = bincode::deserialize(&read_record(&mut reader, length)?)?;
+        let events_start = reader.stream_position()?;
+
+        let offsets = match read_index(&mut reader, events_start, length)? {
+            Some(offsets) => offsets,
+            None => scan_events(&mut reader, events_start, length)?,
+        };
+
+        Ok(Self {
+            reader,
+            length,
+            config,
+            offsets,
+        })
+    }
+
+    /// The config the recorded session was run with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Reads the event at `index`, `None` if it is out of range.
+    pub fn get(&mut self, index: usize) -> Option<Result<AgentMessage, RecordingError>> {
+        let offset = *self.offsets.get(index)?;
+
+        Some(self.read_at(offset))
+    }
+
+    /// Reads all events in recorded order.
+    pub fn messages(&mut self) -> impl Iterator<Item = Result<AgentMessage, RecordingError>> + '_ {
+        (0..self.len()).map(|index| self.get(index).unwrap())
+    }
+
+    fn read_at(&mut self, offset: u64) -> Result<AgentMessage, RecordingError> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+
+        Ok(bincode::deserialize(&read_record(
+            &mut self.reader,
+            self.length,
+        )?)?)
+    }
+}
+
+fn read_index(
+    reader: &mut BufReader<File>,
+    events_start: u64,
+    length: u64,
+) -> Result<Option<Vec<u64>>, RecordingError> {
+    if length < events_start + 16 {
+        return Ok(None);
+    }
+
+    reader.seek(SeekFrom::Start(length - 16))?;
+    let index_offset = read_u64(reader)?;
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != INDEX_MAGIC {
+        return Ok(None);
+    }
+
+    if index_offset < events_start
+        || index_offset
+            .checked_add(8)
+            .is_none_or(|index_end| index_end > length - 16)
+    {
+        return Err(RecordingError::Corrupt("index out of bounds".to_string()));
+    }
+
+    reader.seek(SeekFrom::Start(index_offset))?;
+    let count = read_u64(reader)?;
+    if count > (length - 16 - index_offset - 8) / 8 {
+        return Err(RecordingError::Corrupt("index out of bounds".to_string()));
+    }
+
+    (0..count)
+        .map(|_| read_u64(reader))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Finds the events of an unfinished recording. A trailing event that was only partially
+/// written is dropped.
+fn scan_events(
+    reader: &mut BufReader<File>,
+    events_start: u64,
+    length: u64,
+) -> Result<Vec<u64>, RecordingError> {
+    let mut offsets = Vec::new();
+    let mut position = events_start;
+    reader.seek(SeekFrom::Start(position))?;
+
+    while position + 4 <= length {
+        let record_length = read_u32(reader)? as u64;
+        if position + 4 + record_length > length {
+            break;
+        }
+
+        offsets.push(position);
+        position += 4 + record_length;
+        reader.seek(SeekFrom::Start(position))?;
+    }
+
+    Ok(offsets)
+}
+
+/// Reads a length-prefixed record from a file of `file_length` bytes.
+fn read_record(
+    reader: &mut (impl Read + Seek),
+    file_length: u64,
+) -> Result<Vec<u8>, RecordingError> {
+    let length = read_u32(reader)?;
+    // a corrupt length must not allocate more than the file could hold
+    if reader.stream_position()? + length as u64 > file_length {
+        return Err(RecordingError::Corrupt(
+            "record exceeds the file".to_string(),
+        ));
+    }
+
+    let mut bytes = vec![0; length as usize];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, RecordingError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, RecordingError> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{ClassLoadEvent, test_support::class_load_event};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("aida-{}-{}.rec", name, std::process::id()))
+    }
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            jar = 'app.jar'
+            class_loads = ['com.acme.*']
+            methods = []
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn class_load(timestamp: i64) -> AgentMessage {
+        AgentMessage::ClassLoad(Box::new(ClassLoadEvent {
+            timestamp,
+            ..class_load_event("com.acme.Service")
+        }))
+    }
+
+    fn timestamps(reader: &mut RecordingReader) -> Vec<i64> {
+        reader
+            .messages()
+            .map(|message| match message.unwrap() {
+                AgentMessage::ClassLoad(event) => event.timestamp,
+                AgentMessage::Unload => -1,
+                message => panic!("unexpected message {message:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_events() {
+        let path = temp_path("round-trip");
+        let mut writer = RecordingWriter::create(&path, &config()).unwrap();
+        for timestamp in 0..3 {
+            writer.write(&class_load(timestamp)).unwrap();
+        }
+        writer.write(&AgentMessage::Unload).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = RecordingReader::open(&path).unwrap();
+        assert_eq!(reader.config().jar, "app.jar");
+        assert_eq!(reader.config().class_loads, ["com.acme.*"]);
+        assert_eq!(reader.len(), 4);
+        assert_eq!(timestamps(&mut reader), [0, 1, 2, -1]);
+        assert!(
+            matches!(reader.get(1), Some(Ok(AgentMessage::ClassLoad(event))) if event.timestamp == 1)
+        );
+        assert!(reader.get(4).is_none());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_unfinished_recordings() {
+        let path = temp_path("unfinished");
+        let mut writer = RecordingWriter::create(&path, &config()).unwrap();
+        for timestamp in 0..3 {
+            writer.write(&class_load(timestamp)).unwrap();
+        }
+        writer.flush().unwrap();
+        drop(writer);
+
+        // simulate a crash in the middle of writing an event
+        let length = std::fs::metadata(&path).unwrap().len();
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(length - 3).unwrap();
+
+        let mut reader = RecordingReader::open(&path).unwrap();
+        assert_eq!(timestamps(&mut reader), [0, 1]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_corrupt_index() {
+        let path = temp_path("corrupt-index");
+        let mut writer = RecordingWriter::create(&path, &config()).unwrap();
+        writer.write(&class_load(0)).unwrap();
+        writer.finish().unwrap();
+
+        // point the trailer far past the end of the file
+        let mut bytes = std::fs::read(&path).unwrap();
+        let trailer = bytes.len() - 16;
+        bytes[trailer..trailer + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        assert!(matches!(
+            RecordingReader::open(&path),
+            Err(RecordingError::Corrupt(_))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let path = temp_path("invalid");
+
+        std::fs::write(&path, b"jar = 'app.jar'").unwrap();
+        assert!(matches!(
+            RecordingReader::open(&path),
+            Err(RecordingError::InvalidFormat(_))
+        ));
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&99u32.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        assert!(matches!(
+            RecordingReader::open(&path),
+            Err(RecordingError::UnsupportedVersion(99))
+        ));
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        assert!(matches!(
+            RecordingReader::open(&path),
+            Err(RecordingError::Corrupt(_))
+        ));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
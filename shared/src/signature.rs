@@ -0,0 +1,508 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::descriptor::{BaseType, DescriptorError, Parser};
+
+/// Generic signature of a class, see JVMS §4.7.9.1.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub superclass: ClassTypeSignature,
+    pub interfaces: Vec<ClassTypeSignature>,
+}
+
+/// Generic signature of a method, see JVMS §4.7.9.1.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<JavaTypeSignature>,
+    pub result: ResultSignature,
+    pub throws: Vec<ReferenceTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum ResultSignature {
+    Void,
+    Type(JavaTypeSignature),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TypeParameter {
+    pub name: String,
+    pub class_bound: Option<ReferenceTypeSignature>,
+    pub interface_bounds: Vec<ReferenceTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum JavaTypeSignature {
+    Base(BaseType),
+    Reference(ReferenceTypeSignature),
+}
+
+/// Generic type of a field, local variable or type argument.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum ReferenceTypeSignature {
+    Class(ClassTypeSignature),
+    TypeVariable(String),
+    Array(Box<JavaTypeSignature>),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ClassTypeSignature {
+    pub package: Vec<String>,
+    /// The class followed by the inner classes it is qualified with, outermost first.
+    pub classes: Vec<SimpleClassTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SimpleClassTypeSignature {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum TypeArgument {
+    /// `?`
+    Any,
+    Exact(ReferenceTypeSignature),
+    /// `? extends`
+    Extends(ReferenceTypeSignature),
+    /// `? super`
+    Super(ReferenceTypeSignature),
+}
+
+impl ClassSignature {
+    /// Parses a class signature like `<T:Ljava/lang/Object;>Ljava/lang/Object;`.
+    pub fn new(raw: &str) -> Result<Self, DescriptorError> {
+        let mut parser = Parser::new(raw);
+        let type_parameters = parser.type_parameters()?;
+        let superclass = parser.class_type_signature()?;
+
+        let mut interfaces = Vec::new();
+        while parser.peek().is_some() {
+            interfaces.push(parser.class_type_signature()?);
+        }
+
+        Ok(Self {
+            type_parameters,
+            superclass,
+            interfaces,
+        })
+    }
+
+    /// Type parameters like `<K, V extends Number>`, empty for classes without any.
+    pub fn type_parameters_string(&self, short: bool) -> String {
+        format_type_parameters(&self.type_parameters, short)
+    }
+}
+
+impl MethodSignature {
+    /// Parses a method signature like `<T:Ljava/lang/Object;>(TT;)Ljava/util/List<TT;>;`.
+    pub fn new(raw: &str) -> Result<Self, DescriptorError> {
+        let mut parser = Parser::new(raw);
+        let type_parameters = parser.type_parameters()?;
+        parser.expect('(')?;
+
+        let mut parameters = Vec::new();
+        loop {
+            match parser.peek() {
+                Some(')') => break,
+                Some(_) => parameters.push(parser.java_type_signature()?),
+                None => return Err(parser.error("missing ')'")),
+            }
+        }
+        parser.expect(')')?;
+
+        let result = if parser.peek() == Some('V') {
+            parser.offset += 1;
+            ResultSignature::Void
+        } else {
+            ResultSignature::Type(parser.java_type_signature()?)
+        };
+
+        let mut throws = Vec::new();
+        while parser.peek().is_some() {
+            parser.expect('^')?;
+            match parser.peek() {
+                Some('L') | Some('T') => throws.push(parser.reference_type_signature()?),
+                _ => return Err(parser.error("expected class or type variable after '^'")),
+            }
+        }
+
+        Ok(Self {
+            type_parameters,
+            parameters,
+            result,
+            throws,
+        })
+    }
+
+    /// Java source like declaration, e.g. `<T> java.util.List<T> get(java.util.Map<K, V>)`.
+    pub fn declaration(&self, name: &str) -> String {
+        self.format_declaration(name, false)
+    }
+
+    /// Like [`MethodSignature::declaration`] but with classes without their package, e.g.
+    /// `List<String> get(Map<K, V>)`.
+    pub fn short_declaration(&self, name: &str) -> String {
+        self.format_declaration(name, true)
+    }
+
+    fn format_declaration(&self, name: &str, short: bool) -> String {
+        let mut declaration = format_type_parameters(&self.type_parameters, short);
+        if !declaration.is_empty() {
+            declaration.push(' ');
+        }
+
+        match &self.result {
+            ResultSignature::Void => declaration.push_str("void"),
+            ResultSignature::Type(result) => declaration.push_str(&result.format(short)),
+        }
+
+        let parameters: Vec<String> = self.parameters.iter().map(|p| p.format(short)).collect();
+        declaration.push_str(&format!(" {}({})", name, parameters.join(", ")));
+
+        if !self.throws.is_empty() {
+            let throws: Vec<String> = self.throws.iter().map(|t| t.format(short)).collect();
+            declaration.push_str(&format!(" throws {}", throws.join(", ")));
+        }
+
+        declaration
+    }
+}
+
+impl ReferenceTypeSignature {
+    /// Parses a field signature like `Ljava/util/List<Ljava/lang/String;>;`.
+    pub fn new(raw: &str) -> Result<Self, DescriptorError> {
+        let mut parser = Parser::new(raw);
+        let signature = parser.reference_type_signature()?;
+        parser.finish()?;
+
+        Ok(signature)
+    }
+
+    pub fn to_short_string(&self) -> String {
+        self.format(true)
+    }
+
+    fn format(&self, short: bool) -> String {
+        match self {
+            ReferenceTypeSignature::Class(class) => class.format(short),
+            ReferenceTypeSignature::TypeVariable(name) => name.clone(),
+            ReferenceTypeSignature::Array(component) => format!("{}[]", component.format(short)),
+        }
+    }
+}
+
+impl Display for ReferenceTypeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(false))
+    }
+}
+
+impl JavaTypeSignature {
+    fn format(&self, short: bool) -> String {
+        match self {
+            JavaTypeSignature::Base(base_type) => base_type.to_string(),
+            JavaTypeSignature::Reference(reference) => reference.format(short),
+        }
+    }
+}
+
+impl Display for JavaTypeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format(false))
+    }
+}
+
+impl ClassTypeSignature {
+    fn is_object(&self) -> bool {
+        self.package == ["java", "lang"]
+            && self.classes.len() == 1
+            && self.classes[0].name == "Object"
+            && self.classes[0].type_arguments.is_empty()
+    }
+
+    fn format(&self, short: bool) -> String {
+        let classes: Vec<String> = self.classes.iter().map(|c| c.format(short)).collect();
+        if short || self.package.is_empty() {
+            classes.join(".")
+        } else {
+            format!("{}.{}", self.package.join("."), classes.join("."))
+        }
+    }
+}
+
+impl SimpleClassTypeSignature {
+    fn format(&self, short: bool) -> String {
+        if self.type_arguments.is_empty() {
+            return self.name.clone();
+        }
+
+        let type_arguments: Vec<String> = self
+            .type_arguments
+            .iter()
+            .map(|argument| match argument {
+                TypeArgument::Any => "?".to_string(),
+                TypeArgument::Exact(signature) => signature.format(short),
+                TypeArgument::Extends(signature) => {
+                    format!("? extends {}", signature.format(short))
+                }
+                TypeArgument::Super(signature) => format!("? super {}", signature.format(short)),
+            })
+            .collect();
+        format!("{}<{}>", self.name, type_arguments.join(", "))
+    }
+}
+
+impl TypeParameter {
+    fn format(&self, short: bool) -> String {
+        // a bare `<T>` is compiled to a class bound of Object
+        let bounds: Vec<String> = self
+            .class_bound
+            .iter()
+            .filter(|bound| {
+                !matches!(bound, ReferenceTypeSignature::Class(class) if class.is_object())
+                    || !self.interface_bounds.is_empty()
+            })
+            .chain(&self.interface_bounds)
+            .map(|bound| bound.format(short))
+            .collect();
+
+        if bounds.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} extends {}", self.name, bounds.join(" & "))
+        }
+    }
+}
+
+fn format_type_parameters(type_parameters: &[TypeParameter], short: bool) -> String {
+    if type_parameters.is_empty() {
+        return String::new();
+    }
+
+    let type_parameters: Vec<String> = type_parameters.iter().map(|p| p.format(short)).collect();
+    format!("<{}>", type_parameters.join(", "))
+}
+
+impl Parser<'_> {
+    fn identifier(&mut self) -> Result<String, DescriptorError> {
+        let rest = &self.raw[self.offset..];
+        let length = rest
+            .find(['.', ';', '[', '/', '<', '>', ':'])
+            .unwrap_or(rest.len());
+
+        if length == 0 {
+            return Err(self.error("expected identifier"));
+        }
+
+        self.offset += length;
+        Ok(rest[..length].to_string())
+    }
+
+    fn type_parameters(&mut self) -> Result<Vec<TypeParameter>, DescriptorError> {
+        let mut type_parameters = Vec::new();
+        if self.peek() != Some('<') {
+            return Ok(type_parameters);
+        }
+        self.offset += 1;
+
+        loop {
+            let name = self.identifier()?;
+            self.expect(':')?;
+
+            let class_bound = match self.peek() {
+                Some('L') | Some('T') | Some('[') => Some(self.reference_type_signature()?),
+                _ => None,
+            };
+
+            let mut interface_bounds = Vec::new();
+            while self.peek() == Some(':') {
+                self.offset += 1;
+                interface_bounds.push(self.reference_type_signature()?);
+            }
+
+            type_parameters.push(TypeParameter {
+                name,
+                class_bound,
+                interface_bounds,
+            });
+
+            if self.peek() == Some('>') {
+                self.offset += 1;
+                return Ok(type_parameters);
+            }
+        }
+    }
+
+    fn java_type_signature(&mut self) -> Result<JavaTypeSignature, DescriptorError> {
+        match self.peek().and_then(BaseType::from_descriptor) {
+            Some(base_type) => {
+                self.offset += 1;
+                Ok(JavaTypeSignature::Base(base_type))
+            }
+            None => Ok(JavaTypeSignature::Reference(
+                self.reference_type_signature()?,
+            )),
+        }
+    }
+
+    fn reference_type_signature(&mut self) -> Result<ReferenceTypeSignature, DescriptorError> {
+        match self.peek() {
+            Some('L') => Ok(ReferenceTypeSignature::Class(self.class_type_signature()?)),
+            Some('T') => {
+                self.offset += 1;
+                let name = self.identifier()?;
+                self.expect(';')?;
+                Ok(ReferenceTypeSignature::TypeVariable(name))
+            }
+            Some('[') => {
+                self.offset += 1;
+                Ok(ReferenceTypeSignature::Array(Box::new(
+                    self.java_type_signature()?,
+                )))
+            }
+            Some(c) => Err(self.error(format!("unknown reference type '{c}'"))),
+            None => Err(self.error("expected reference type, found end of input")),
+        }
+    }
+
+    fn class_type_signature(&mut self) -> Result<ClassTypeSignature, DescriptorError> {
+        self.expect('L')?;
+
+        let mut package = Vec::new();
+        let mut name = self.identifier()?;
+        while self.peek() == Some('/') {
+            self.offset += 1;
+            package.push(name);
+            name = self.identifier()?;
+        }
+
+        let mut classes = vec![self.simple_class_type_signature(name)?];
+        while self.peek() == Some('.') {
+            self.offset += 1;
+            let name = self.identifier()?;
+            classes.push(self.simple_class_type_signature(name)?);
+        }
+        self.expect(';')?;
+
+        Ok(ClassTypeSignature { package, classes })
+    }
+
+    fn simple_class_type_signature(
+        &mut self,
+        name: String,
+    ) -> Result<SimpleClassTypeSignature, DescriptorError> {
+        let mut type_arguments = Vec::new();
+        if self.peek() == Some('<') {
+            self.offset += 1;
+
+            loop {
+                let type_argument = match self.peek() {
+                    Some('*') => {
+                        self.offset += 1;
+                        TypeArgument::Any
+                    }
+                    Some('+') => {
+                        self.offset += 1;
+                        TypeArgument::Extends(self.reference_type_signature()?)
+                    }
+                    Some('-') => {
+                        self.offset += 1;
+                        TypeArgument::Super(self.reference_type_signature()?)
+                    }
+                    _ => TypeArgument::Exact(self.reference_type_signature()?),
+                };
+                type_arguments.push(type_argument);
+
+                if self.peek() == Some('>') {
+                    self.offset += 1;
+                    break;
+                }
+            }
+        }
+
+        Ok(SimpleClassTypeSignature {
+            name,
+            type_arguments,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_method_signatures() {
+        let signature =
+            MethodSignature::new("(Ljava/util/Map<TK;TV;>;)Ljava/util/List<Ljava/lang/String;>;")
+                .unwrap();
+        assert_eq!(
+            signature.short_declaration("get"),
+            "List<String> get(Map<K, V>)"
+        );
+        assert_eq!(
+            signature.declaration("get"),
+            "java.util.List<java.lang.String> get(java.util.Map<K, V>)"
+        );
+
+        let signature = MethodSignature::new(
+            "<T::Ljava/lang/Comparable<-TT;>;>(Ljava/util/Collection<+TT;>;[I)TT;^TX;",
+        )
+        .unwrap();
+        assert_eq!(
+            signature.short_declaration("max"),
+            "<T extends Comparable<? super T>> T max(Collection<? extends T>, int[]) throws X"
+        );
+
+        let signature =
+            MethodSignature::new("<T:Ljava/lang/Object;>(Ljava/util/List<*>;)V").unwrap();
+        assert_eq!(
+            signature.short_declaration("clear"),
+            "<T> void clear(List<?>)"
+        );
+    }
+
+    #[test]
+    fn parses_class_signatures() {
+        let signature = ClassSignature::new(
+            "<K:Ljava/lang/Object;V:Ljava/lang/Number;>Ljava/util/AbstractMap<TK;TV;>;Ljava/lang/Cloneable;",
+        )
+        .unwrap();
+        assert_eq!(
+            signature.type_parameters_string(true),
+            "<K, V extends Number>"
+        );
+        assert_eq!(signature.superclass.format(true), "AbstractMap<K, V>");
+        assert_eq!(signature.interfaces.len(), 1);
+    }
+
+    #[test]
+    fn parses_field_signatures() {
+        let signature =
+            ReferenceTypeSignature::new("Ljava/util/Map<TK;TV;>.Entry<TK;TV;>;").unwrap();
+        assert_eq!(signature.to_short_string(), "Map<K, V>.Entry<K, V>");
+        assert_eq!(signature.to_string(), "java.util.Map<K, V>.Entry<K, V>");
+
+        let signature = ReferenceTypeSignature::new("[[TT;").unwrap();
+        assert_eq!(signature.to_short_string(), "T[][]");
+    }
+
+    #[test]
+    fn reports_invalid_signatures() {
+        assert_eq!(MethodSignature::new("(TT)V").unwrap_err().offset, 5);
+        assert_eq!(MethodSignature::new("<>()V").unwrap_err().offset, 1);
+        assert_eq!(MethodSignature::new("()V^I").unwrap_err().offset, 4);
+        assert_eq!(
+            ClassSignature::new("Ljava/util/List<>;")
+                .unwrap_err()
+                .offset,
+            16
+        );
+        assert_eq!(ReferenceTypeSignature::new("I").unwrap_err().offset, 0);
+        assert_eq!(ReferenceTypeSignature::new("TT;;").unwrap_err().offset, 3);
+    }
+}
@@ -0,0 +1,339 @@
+//! Latency statistics per method, aggregated from the invocations of a [`CallTree`].
+
+use std::{collections::HashMap, fmt::Display, io::Write, ops::Range};
+
+use crate::{
+    MethodEvent,
+    call_tree::{CallNode, CallTree},
+    class::ClassIdentifier,
+    descriptor::MethodDescriptor,
+    format_duration,
+};
+
+/// Identifies a method, overloads are told apart by their descriptor.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MethodKey {
+    pub class_identifier: ClassIdentifier,
+    pub name: String,
+    pub descriptor: MethodDescriptor,
+}
+
+impl MethodKey {
+    pub fn of(method_event: &MethodEvent) -> Self {
+        Self {
+            class_identifier: method_event.class_identifier.clone(),
+            name: method_event.name.clone(),
+            descriptor: method_event.descriptor.clone(),
+        }
+    }
+}
+
+impl Display for MethodKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}{}",
+            self.class_identifier,
+            self.name,
+            self.descriptor.to_short_string()
+        )
+    }
+}
+
+/// One invocation that returned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    /// Index of the entry event in the events the statistics were built from.
+    pub entry: usize,
+    /// Time including callees in microseconds.
+    pub duration: i64,
+    pub exclusive: i64,
+}
+
+/// Invocations with a duration in `lower..upper` microseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    pub lower: i64,
+    pub upper: i64,
+    /// Indices into [`MethodStats::invocations`].
+    pub invocations: Range<usize>,
+}
+
+/// Latencies of the invocations of one method, all times in microseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodStats {
+    pub key: MethodKey,
+    /// Invocations that returned, sorted by duration.
+    pub invocations: Vec<Invocation>,
+    /// Invocations that have not returned (yet), they are not part of any latency.
+    pub running: usize,
+    pub total: i64,
+    pub exclusive: i64,
+    pub mean: i64,
+    pub min: i64,
+    pub max: i64,
+    pub p50: i64,
+    pub p90: i64,
+    pub p99: i64,
+}
+
+impl MethodStats {
+    /// Statistics of every method with at least one returned invocation, slowest in total first.
+    pub fn collect(call_tree: &CallTree, method_events: &[MethodEvent]) -> Vec<Self> {
+        Self::collect_by(call_tree, method_events, MethodKey::of)
+    }
+
+    /// Like [`Self::collect`], but invocations are grouped by `key` instead of their method.
+    pub fn collect_by(
+        call_tree: &CallTree,
+        method_events: &[MethodEvent],
+        key: impl Fn(&MethodEvent) -> MethodKey,
+    ) -> Vec<Self> {
+        let mut invocations: HashMap<MethodKey, (Vec<Invocation>, usize)> = HashMap::new();
+        for thread_call_tree in &call_tree.threads {
+            for node in &thread_call_tree.roots {
+                collect_invocations(&mut invocations, node, method_events, &key);
+            }
+        }
+
+        let mut stats: Vec<Self> = invocations
+            .into_iter()
+            .filter_map(|(key, (invocations, running))| Self::new(key, invocations, running))
+            .collect();
+        stats.sort_by(|a, b| {
+            b.total
+                .cmp(&a.total)
+                .then_with(|| a.key.name.cmp(&b.key.name))
+        });
+        stats
+    }
+
+    fn new(key: MethodKey, mut invocations: Vec<Invocation>, running: usize) -> Option<Self> {
+        if invocations.is_empty() {
+            return None;
+        }
+
+        invocations.sort_by_key(|invocation| invocation.duration);
+        let total: i64 = invocations.iter().map(|i| i.duration).sum();
+
+        Some(Self {
+            key,
+            running,
+            total,
+            exclusive: invocations.iter().map(|i| i.exclusive).sum(),
+            mean: total / invocations.len() as i64,
+            min: invocations[0].duration,
+            max: invocations[invocations.len() - 1].duration,
+            p50: percentile(&invocations, 50.0),
+            p90: percentile(&invocations, 90.0),
+            p99: percentile(&invocations, 99.0),
+            invocations,
+        })
+    }
+
+    pub fn calls(&self) -> usize {
+        self.invocations.len()
+    }
+
+    /// Latency at or below which `p` percent of the invocations returned.
+    pub fn percentile(&self, p: f64) -> i64 {
+        percentile(&self.invocations, p)
+    }
+
+    /// Log-scaled buckets from the fastest to the slowest invocation, each `buckets_per_decade`-th
+    /// bound is a power of ten. Empty buckets in between are kept so the scale stays continuous.
+    pub fn histogram(&self, buckets_per_decade: u32) -> Vec<Bucket> {
+        let mut buckets = Vec::new();
+
+        let first = bucket_index(self.min, buckets_per_decade);
+        let last = bucket_index(self.max, buckets_per_decade);
+        for index in first..=last {
+            let lower = bucket_bound(index, buckets_per_decade);
+            let upper = bucket_bound(index + 1, buckets_per_decade);
+            // bounds are rounded to whole microseconds, fine buckets can collapse
+            if lower == upper {
+                continue;
+            }
+
+            buckets.push(Bucket {
+                lower,
+                upper,
+                invocations: self.invocations.partition_point(|i| i.duration < lower)
+                    ..self.invocations.partition_point(|i| i.duration < upper),
+            });
+        }
+
+        buckets
+    }
+}
+
+fn collect_invocations(
+    invocations: &mut HashMap<MethodKey, (Vec<Invocation>, usize)>,
+    node: &CallNode,
+    method_events: &[MethodEvent],
+    key: &impl Fn(&MethodEvent) -> MethodKey,
+) {
+    let (method_invocations, running) = invocations
+        .entry(key(&method_events[node.entry]))
+        .or_default();
+
+    match (node.inclusive_time(), node.exclusive_time()) {
+        (Some(duration), Some(exclusive)) => method_invocations.push(Invocation {
+            entry: node.entry,
+            duration,
+            exclusive,
+        }),
+        _ => *running += 1,
+    }
+
+    for child in &node.children {
+        collect_invocations(invocations, child, method_events, key);
+    }
+}
+
+/// Lower bound of the bucket at `index`, the bucket before the first one holds zero durations.
+fn bucket_bound(index: i32, buckets_per_decade: u32) -> i64 {
+    if index < 0 {
+        0
+    } else {
+        10f64.powf(index as f64 / buckets_per_decade as f64).ceil() as i64
+    }
+}
+
+fn bucket_index(duration: i64, buckets_per_decade: u32) -> i32 {
+    if duration < 1 {
+        return -1;
+    }
+
+    // the logarithm is only an estimate at the bounds
+    let mut index = ((duration as f64).log10() * buckets_per_decade as f64).floor() as i32;
+    while duration < bucket_bound(index, buckets_per_decade) {
+        index -= 1;
+    }
+    while duration >= bucket_bound(index + 1, buckets_per_decade) {
+        index += 1;
+    }
+
+    index
+}
+
+/// Nearest-rank percentile of invocations sorted by duration.
+pub(crate) fn percentile(invocations: &[Invocation], p: f64) -> i64 {
+    let rank = (p / 100.0 * invocations.len() as f64).ceil() as usize;
+    invocations[rank.clamp(1, invocations.len()) - 1].duration
+}
+
+/// Prints the statistics as an aligned table.
+pub fn write_table(mut out: impl Write, stats: &[MethodStats]) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{:>8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}  method",
+        "calls", "total", "mean", "min", "max", "p50", "p90", "p99", "excl"
+    )?;
+
+    for method_stats in stats {
+        writeln!(
+            out,
+            "{:>8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}  {}",
+            method_stats.calls(),
+            format_duration(method_stats.total),
+            format_duration(method_stats.mean),
+            format_duration(method_stats.min),
+            format_duration(method_stats.max),
+            format_duration(method_stats.p50),
+            format_duration(method_stats.p90),
+            format_duration(method_stats.p99),
+            format_duration(method_stats.exclusive),
+            method_stats.key
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MethodEventType, test_support::method_event};
+
+    #[test]
+    fn aggregates_by_method() {
+        let mut events = vec![method_event(0, "outer", MethodEventType::Entry)];
+        for (i, duration) in [30, 10, 20, 40].into_iter().enumerate() {
+            let start = 100 * (i as i64 + 1);
+            events.push(method_event(start, "inner", MethodEventType::Entry));
+            events.push(method_event(
+                start + duration,
+                "inner",
+                MethodEventType::Exit,
+            ));
+        }
+        events.push(method_event(1000, "outer", MethodEventType::Exit));
+        events.push(method_event(1100, "running", MethodEventType::Entry));
+
+        let stats = MethodStats::collect(&CallTree::build(&events), &events);
+        assert_eq!(stats.len(), 2);
+
+        let outer = &stats[0];
+        assert_eq!(outer.key.name, "outer");
+        assert_eq!(outer.total, 1000);
+        assert_eq!(outer.exclusive, 900);
+
+        let inner = &stats[1];
+        assert_eq!(inner.calls(), 4);
+        assert_eq!(inner.running, 0);
+        assert_eq!(inner.total, 100);
+        assert_eq!(inner.mean, 25);
+        assert_eq!((inner.min, inner.max), (10, 40));
+        assert_eq!((inner.p50, inner.p90, inner.p99), (20, 40, 40));
+        assert_eq!(inner.invocations[0].entry, 3);
+    }
+
+    #[test]
+    fn log_scaled_histogram() {
+        let invocations = [1, 2, 9, 10, 100, 150, 999]
+            .into_iter()
+            .map(|duration| Invocation {
+                entry: 0,
+                duration,
+                exclusive: duration,
+            })
+            .collect();
+        let key = MethodKey {
+            class_identifier: ClassIdentifier::parse("com.acme.Service").unwrap(),
+            name: "run".to_string(),
+            descriptor: MethodDescriptor::new("()V").unwrap(),
+        };
+        let stats = MethodStats::new(key, invocations, 0).unwrap();
+
+        let buckets: Vec<(i64, i64, usize)> = stats
+            .histogram(1)
+            .into_iter()
+            .map(|b| (b.lower, b.upper, b.invocations.len()))
+            .collect();
+        assert_eq!(buckets, [(1, 10, 3), (10, 100, 1), (100, 1000, 3)]);
+
+        let buckets = stats.histogram(2);
+        assert_eq!(buckets.len(), 6);
+        assert_eq!((buckets[0].lower, buckets[0].upper), (1, 4));
+        assert_eq!(buckets[5].invocations, 6..7);
+        assert_eq!(bucket_index(0, 2), -1);
+        assert_eq!(bucket_index(1000, 3), 9);
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let invocations: Vec<Invocation> = (1..=100)
+            .map(|duration| Invocation {
+                entry: 0,
+                duration,
+                exclusive: duration,
+            })
+            .collect();
+
+        assert_eq!(percentile(&invocations, 0.0), 1);
+        assert_eq!(percentile(&invocations, 50.0), 50);
+        assert_eq!(percentile(&invocations, 99.0), 99);
+        assert_eq!(percentile(&invocations, 100.0), 100);
+    }
+}
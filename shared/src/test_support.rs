@@ -0,0 +1,57 @@
+//! Events for the tests of this crate.
+
+use crate::{
+    ClassLoadEvent, MethodEvent, MethodEventType, ThreadInfo, class::ClassIdentifier,
+    descriptor::MethodDescriptor,
+};
+
+/// Thread `id`, named `thread-<id>`.
+pub fn thread(id: u64) -> ThreadInfo {
+    ThreadInfo {
+        id,
+        name: format!("thread-{id}"),
+    }
+}
+
+/// An event of `com.acme.Service.<name>()V` on thread 1, without arguments or return value.
+pub fn method_event(timestamp: i64, name: &str, method_event_type: MethodEventType) -> MethodEvent {
+    MethodEvent {
+        timestamp,
+        name: name.to_string(),
+        class_identifier: ClassIdentifier::parse("com.acme.Service").unwrap(),
+        descriptor: MethodDescriptor::new("()V").unwrap(),
+        signature: None,
+        method_event_type,
+        thread: thread(1),
+        arguments: Vec::new(),
+        return_value: None,
+        was_popped_by_exception: false,
+    }
+}
+
+/// A load of `class` at timestamp 0 on thread 1.
+pub fn class_load_event(class: &str) -> ClassLoadEvent {
+    ClassLoadEvent {
+        timestamp: 0,
+        class_identifier: ClassIdentifier::parse(class).unwrap(),
+        signature: None,
+        thread: thread(1),
+    }
+}
+
+impl MethodEvent {
+    pub fn on_thread(mut self, id: u64) -> Self {
+        self.thread = thread(id);
+        self
+    }
+
+    pub fn with_class(mut self, class: &str) -> Self {
+        self.class_identifier = ClassIdentifier::parse(class).unwrap();
+        self
+    }
+
+    pub fn with_descriptor(mut self, descriptor: &str) -> Self {
+        self.descriptor = MethodDescriptor::new(descriptor).unwrap();
+        self
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::class::ClassIdentifier;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(i8),
+    Char(u16),
+    Double(f64),
+    Float(f32),
+    Int(i32),
+    Long(i64),
+    Short(i16),
+    Boolean(bool),
+    Object(ObjectValue),
+    Null,
+    /// The value could not be read, e.g. because the frame belongs to a native method.
+    Unavailable,
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Byte(value) => write!(f, "{}", value),
+            Value::Char(value) => match char::from_u32(*value as u32) {
+                Some(c) => write!(f, "'{}'", c.escape_default()),
+                None => write!(f, "'\\u{:04x}'", value),
+            },
+            Value::Double(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Long(value) => write!(f, "{}", value),
+            Value::Short(value) => write!(f, "{}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Object(object) => write!(f, "{}", object),
+            Value::Null => write!(f, "null"),
+            Value::Unavailable => write!(f, "?"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ObjectValue {
+    pub class_identifier: ClassIdentifier,
+    pub identity_hash: i32,
+    /// Result of `toString()`, truncated by the agent.
+    pub to_string: Option<String>,
+}
+
+impl ObjectValue {
+    fn is_string(&self) -> bool {
+        self.class_identifier.to_string() == "java.lang.String"
+    }
+}
+
+impl Display for ObjectValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.to_string {
+            Some(to_string) if self.is_string() => write!(f, "{:?}", to_string),
+            Some(to_string) => write!(f, "{}", to_string),
+            None => write!(
+                f,
+                "{}@{:x}",
+                self.class_identifier.short_name(),
+                self.identity_hash
+            ),
+        }
+    }
+}
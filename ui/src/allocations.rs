@@ -0,0 +1,267 @@
+use eframe::egui::{self, Color32, RichText};
+use shared::{
+    AllocationEvent, AllocationEventType, MethodLocation,
+    allocation::{self, Allocations, format_bytes},
+    class::ClassIdentifier,
+};
+
+/// Rows shown per table, the rest is summed up in one line.
+const MAX_ROWS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Grouping {
+    Class,
+    Site,
+}
+
+/// Allocations aggregated by class or by allocation site.
+pub struct AllocationView {
+    grouping: Grouping,
+    classes: Vec<Allocations<ClassIdentifier>>,
+    sites: Vec<Allocations<Option<MethodLocation>>>,
+    /// Number of allocation events and the thread filter the tables were built from.
+    built_from: Option<(usize, Option<u64>)>,
+    /// Class whose allocation sites are shown.
+    selected_class: Option<ClassIdentifier>,
+    selected_class_sites: Vec<Allocations<Option<MethodLocation>>>,
+    /// Site whose stack trace is shown.
+    selected_site: Option<Option<MethodLocation>>,
+}
+
+impl Default for AllocationView {
+    fn default() -> Self {
+        Self {
+            grouping: Grouping::Class,
+            classes: Vec::new(),
+            sites: Vec::new(),
+            built_from: None,
+            selected_class: None,
+            selected_class_sites: Vec::new(),
+            selected_site: None,
+        }
+    }
+}
+
+impl AllocationView {
+    pub fn clear(&mut self) {
+        self.classes.clear();
+        self.sites.clear();
+        self.built_from = None;
+        self.selected_class = None;
+        self.selected_class_sites.clear();
+        self.selected_site = None;
+    }
+
+    fn rebuild(&mut self, allocation_events: &[AllocationEvent], thread_filter: Option<u64>) {
+        let events = || {
+            allocation_events
+                .iter()
+                .filter(move |e| thread_filter.is_none_or(|id| id == e.thread.id))
+        };
+
+        self.classes = allocation::by_class(events());
+        self.sites = allocation::by_site(events());
+        self.selected_class_sites = match &self.selected_class {
+            Some(class) => allocation::by_site(events().filter(|e| &e.class_identifier == class)),
+            None => Vec::new(),
+        };
+    }
+
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        allocation_events: &[AllocationEvent],
+        sampling_interval: Option<i32>,
+        thread_filter: Option<u64>,
+    ) {
+        let built_from = Some((allocation_events.len(), thread_filter));
+        if self.built_from != built_from {
+            self.rebuild(allocation_events, thread_filter);
+            self.built_from = built_from;
+        }
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.grouping, Grouping::Class, "By class");
+            ui.selectable_value(&mut self.grouping, Grouping::Site, "By site");
+
+            if let Some(sampling_interval) = sampling_interval {
+                ui.label(
+                    RichText::new(format!(
+                        "sampled about every {}, allocations by the VM are all counted",
+                        format_bytes(sampling_interval as i64)
+                    ))
+                    .color(Color32::GRAY),
+                );
+            }
+        });
+
+        match self.grouping {
+            Grouping::Class => {
+                let mut clicked = None;
+                egui::ScrollArea::vertical()
+                    .id_salt("allocations_by_class")
+                    .auto_shrink([false, true])
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        clicked = show_table(
+                            ui,
+                            "allocations_by_class",
+                            &self.classes,
+                            |class| self.selected_class.as_ref() == Some(class),
+                            |ui, class, selected| {
+                                ui.selectable_label(
+                                    selected,
+                                    RichText::new(class.to_string()).color(Color32::WHITE),
+                                )
+                                .on_hover_text("Click to show the allocation sites")
+                            },
+                        );
+                    });
+
+                if let Some(class) = clicked {
+                    self.selected_class =
+                        (self.selected_class.as_ref() != Some(&class)).then_some(class);
+                    self.selected_site = None;
+                    self.rebuild(allocation_events, thread_filter);
+                }
+
+                if let Some(class) = &self.selected_class {
+                    ui.label(
+                        RichText::new(format!("Sites allocating {}", class)).color(Color32::GRAY),
+                    );
+                    self.show_sites(ui, allocation_events, thread_filter, Some(class.clone()));
+                }
+            }
+            Grouping::Site => self.show_sites(ui, allocation_events, thread_filter, None),
+        }
+    }
+
+    /// Shows all sites or those allocating `class`, and the stack trace of the selected site.
+    fn show_sites(
+        &mut self,
+        ui: &mut egui::Ui,
+        allocation_events: &[AllocationEvent],
+        thread_filter: Option<u64>,
+        class: Option<ClassIdentifier>,
+    ) {
+        let sites = match class {
+            Some(_) => &self.selected_class_sites,
+            None => &self.sites,
+        };
+
+        let mut clicked = None;
+        egui::ScrollArea::vertical()
+            .id_salt(("allocation_sites", class.is_some()))
+            .auto_shrink([false, true])
+            .max_height(300.0)
+            .show(ui, |ui| {
+                clicked = show_table(
+                    ui,
+                    ("allocation_sites", class.is_some()),
+                    sites,
+                    |site| self.selected_site.as_ref() == Some(site),
+                    |ui, site, selected| match site {
+                        Some(location) => ui
+                            .selectable_label(
+                                selected,
+                                RichText::new(location.to_string()).color(Color32::WHITE),
+                            )
+                            .on_hover_text(format!(
+                                "{}\nClick to show a stack trace",
+                                location.class_identifier
+                            )),
+                        None => ui.selectable_label(selected, "no Java frames"),
+                    },
+                );
+            });
+
+        if let Some(site) = clicked {
+            self.selected_site = (self.selected_site.as_ref() != Some(&site)).then_some(site);
+        }
+
+        let Some(site) = &self.selected_site else {
+            return;
+        };
+
+        // the largest allocation at the site stands for all of them
+        let example = allocation_events
+            .iter()
+            .filter(|e| thread_filter.is_none_or(|id| id == e.thread.id))
+            .filter(|e| {
+                class
+                    .as_ref()
+                    .is_none_or(|class| &e.class_identifier == class)
+            })
+            .filter(|e| e.stack_trace.first() == site.as_ref())
+            .max_by_key(|e| e.size);
+        let Some(example) = example else {
+            return;
+        };
+
+        ui.label(
+            RichText::new(format!(
+                "{}{} of {} on {}",
+                match example.allocation_event_type {
+                    AllocationEventType::Sampled => "",
+                    AllocationEventType::VirtualMachine => "VM allocation ",
+                },
+                format_bytes(example.size),
+                example.class_identifier,
+                example.thread.name
+            ))
+            .color(Color32::GRAY),
+        );
+        for frame in &example.stack_trace {
+            ui.label(RichText::new(format!("at {}", frame)).monospace())
+                .on_hover_text(frame.class_identifier.to_string());
+        }
+    }
+}
+
+/// Shows count, bytes and share per key, returns the key whose label was clicked.
+fn show_table<K: Clone>(
+    ui: &mut egui::Ui,
+    id_salt: impl std::hash::Hash,
+    allocations: &[Allocations<K>],
+    is_selected: impl Fn(&K) -> bool,
+    key_label: impl Fn(&mut egui::Ui, &K, bool) -> egui::Response,
+) -> Option<K> {
+    if allocations.is_empty() {
+        ui.label("No allocations");
+        return None;
+    }
+
+    let total: i64 = allocations.iter().map(|a| a.bytes).sum();
+    let mut clicked = None;
+
+    egui::Grid::new(id_salt).striped(true).show(ui, |ui| {
+        for title in ["count", "bytes", "share", ""] {
+            ui.label(RichText::new(title).strong());
+        }
+        ui.end_row();
+
+        for entry in allocations.iter().take(MAX_ROWS) {
+            ui.label(entry.count.to_string());
+            ui.label(format_bytes(entry.bytes));
+            ui.label(format!(
+                "{:.1}%",
+                entry.bytes as f64 * 100.0 / total.max(1) as f64
+            ));
+            if key_label(ui, &entry.key, is_selected(&entry.key)).clicked() {
+                clicked = Some(entry.key.clone());
+            }
+            ui.end_row();
+        }
+
+        if allocations.len() > MAX_ROWS {
+            let rest = &allocations[MAX_ROWS..];
+            ui.label(rest.iter().map(|a| a.count).sum::<usize>().to_string());
+            ui.label(format_bytes(rest.iter().map(|a| a.bytes).sum()));
+            ui.label("");
+            ui.label(RichText::new(format!("{} more", rest.len())).color(Color32::GRAY));
+            ui.end_row();
+        }
+    });
+
+    clicked
+}
@@ -0,0 +1,63 @@
+use eframe::egui::{self, Color32, RichText};
+use shared::call_tree::{CallNode, CallTree};
+
+use crate::{method_label, thread_label};
+
+pub fn show(
+    ui: &mut egui::Ui,
+    call_tree: &CallTree,
+    method_events: &[shared::MethodEvent],
+    thread_filter: Option<u64>,
+) {
+    for thread_call_tree in &call_tree.threads {
+        if thread_filter.is_some_and(|id| id != thread_call_tree.thread.id) {
+            continue;
+        }
+
+        egui::CollapsingHeader::new(thread_label(&thread_call_tree.thread))
+            .id_salt(("call_tree", thread_call_tree.thread.id))
+            .default_open(true)
+            .show(ui, |ui| {
+                for node in &thread_call_tree.roots {
+                    show_node(ui, node, method_events);
+                }
+            });
+    }
+}
+
+fn show_node(ui: &mut egui::Ui, node: &CallNode, method_events: &[shared::MethodEvent]) {
+    let entry = &method_events[node.entry];
+
+    let times = match (node.inclusive_time(), node.exclusive_time()) {
+        (Some(inclusive), Some(exclusive)) => format!(
+            "{} incl, {} excl",
+            shared::format_duration(inclusive),
+            shared::format_duration(exclusive)
+        ),
+        _ => "running".to_string(),
+    };
+
+    let threw = node
+        .exit
+        .is_some_and(|exit| method_events[exit].was_popped_by_exception);
+    let color = if threw {
+        Color32::ORANGE
+    } else {
+        Color32::WHITE
+    };
+
+    let text = RichText::new(format!("{}  {}", method_label(entry), times)).color(color);
+
+    if node.children.is_empty() {
+        ui.label(text)
+            .on_hover_text(entry.class_identifier.to_string());
+    } else {
+        egui::CollapsingHeader::new(text)
+            .id_salt(("call_node", node.entry))
+            .show(ui, |ui| {
+                for child in &node.children {
+                    show_node(ui, child, method_events);
+                }
+            });
+    }
+}
@@ -0,0 +1,229 @@
+use std::path::Path;
+
+use eframe::egui::{self, Color32, RichText};
+use shared::{
+    AgentMessage, ClassLoadEvent, MethodEvent,
+    diff::{MethodDiff, Run, RunDiff},
+    format_duration,
+    recording::{RecordingError, RecordingReader},
+    stats::MethodStats,
+};
+
+/// Mean latency changes below this fraction are not highlighted.
+const SIGNIFICANT_CHANGE: f64 = 0.1;
+
+/// Events of a recorded run the current events are compared with.
+struct Baseline {
+    path: String,
+    class_load_events: Vec<ClassLoadEvent>,
+    method_events: Vec<MethodEvent>,
+}
+
+impl Baseline {
+    fn load(path: &str) -> Result<Self, RecordingError> {
+        let mut reader = RecordingReader::open(Path::new(path))?;
+
+        let mut class_load_events = Vec::new();
+        let mut method_events = Vec::new();
+        for msg in reader.messages() {
+            match msg? {
+                AgentMessage::ClassLoad(event) => class_load_events.push(*event),
+                AgentMessage::MethodEvent(event) => method_events.push(*event),
+                AgentMessage::Exception(_) | AgentMessage::Allocation(_) | AgentMessage::Unload => {
+                }
+            }
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            class_load_events,
+            method_events,
+        })
+    }
+}
+
+/// Differences between a recording and the current events.
+#[derive(Default)]
+pub struct DiffView {
+    baseline_path: String,
+    baseline: Option<Baseline>,
+    error: Option<String>,
+    diff: Option<RunDiff>,
+    /// Number of class load and method events `diff` was built from.
+    built_from: Option<(usize, usize)>,
+}
+
+impl DiffView {
+    /// Drops the diff of the current events, the baseline is kept.
+    pub fn clear(&mut self) {
+        self.diff = None;
+        self.built_from = None;
+    }
+
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        class_load_events: &[ClassLoadEvent],
+        method_events: &[MethodEvent],
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("Compare with recording");
+            ui.text_edit_singleline(&mut self.baseline_path);
+            if ui.button("Load").clicked() && !self.baseline_path.is_empty() {
+                match Baseline::load(&self.baseline_path) {
+                    Ok(baseline) => {
+                        self.baseline = Some(baseline);
+                        self.error = None;
+                        self.built_from = None;
+                    }
+                    Err(err) => {
+                        self.error = Some(format!("failed to open {}: {}", self.baseline_path, err))
+                    }
+                }
+            }
+
+            if let Some(error) = &self.error {
+                ui.label(RichText::new(error).color(Color32::RED));
+            }
+        });
+
+        let Some(baseline) = &self.baseline else {
+            return;
+        };
+
+        let built_from = Some((class_load_events.len(), method_events.len()));
+        if self.built_from != built_from {
+            self.diff = Some(RunDiff::new(
+                Run {
+                    class_load_events: &baseline.class_load_events,
+                    method_events: &baseline.method_events,
+                },
+                Run {
+                    class_load_events,
+                    method_events,
+                },
+            ));
+            self.built_from = built_from;
+        }
+        let Some(diff) = &self.diff else {
+            return;
+        };
+
+        ui.label(RichText::new(format!("{} → current events", baseline.path)).color(Color32::GRAY));
+
+        egui::CollapsingHeader::new(format!("Methods ({})", diff.methods.len()))
+            .id_salt("diff_methods")
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .id_salt("diff_methods")
+                    .auto_shrink([false, true])
+                    .max_height(300.0)
+                    .show(ui, |ui| show_methods(ui, &diff.methods));
+            });
+
+        show_list(
+            ui,
+            "Classes only loaded by the recording",
+            &diff.classes_only_before,
+        );
+        show_list(ui, "Classes only loaded now", &diff.classes_only_after);
+        show_list(ui, "Missing call paths", &diff.paths_only_before);
+        show_list(ui, "New call paths", &diff.paths_only_after);
+    }
+}
+
+fn show_methods(ui: &mut egui::Ui, methods: &[MethodDiff]) {
+    egui::Grid::new("diff_methods")
+        .striped(true)
+        .show(ui, |ui| {
+            for title in ["calls", "mean", "p99", "total", "change", "method"] {
+                ui.label(RichText::new(title).strong());
+            }
+            ui.end_row();
+
+            let duration = |stats: &Option<MethodStats>, value: fn(&MethodStats) -> i64| {
+                stats
+                    .as_ref()
+                    .map_or("–".to_string(), |stats| format_duration(value(stats)))
+            };
+
+            for method in methods {
+                ui.label(format!(
+                    "{} → {}",
+                    method.calls_before(),
+                    method.calls_after()
+                ));
+                ui.label(format!(
+                    "{} → {}",
+                    duration(&method.before, |s| s.mean),
+                    duration(&method.after, |s| s.mean)
+                ));
+                ui.label(format!(
+                    "{} → {}",
+                    duration(&method.before, |s| s.p99),
+                    duration(&method.after, |s| s.p99)
+                ));
+
+                let total_change = method.total_change();
+                ui.label(format!(
+                    "{}{}",
+                    if total_change > 0 { "+" } else { "" },
+                    format_duration(total_change)
+                ));
+
+                match (method.mean_change(), &method.before, &method.after) {
+                    (Some(change), _, _) => {
+                        let color = if change > SIGNIFICANT_CHANGE {
+                            Color32::RED
+                        } else if change < -SIGNIFICANT_CHANGE {
+                            Color32::GREEN
+                        } else {
+                            Color32::GRAY
+                        };
+                        ui.label(RichText::new(format!("{:+.0}%", change * 100.0)).color(color))
+                            .on_hover_text("Change of the mean latency");
+                    }
+                    (None, None, _) => {
+                        ui.label(RichText::new("new").color(Color32::YELLOW));
+                    }
+                    (None, _, None) => {
+                        ui.label(RichText::new("missing").color(Color32::YELLOW));
+                    }
+                    (None, _, _) => {
+                        ui.label("");
+                    }
+                }
+
+                let key = &method.key;
+                ui.label(
+                    RichText::new(format!(
+                        "{}.{}{}",
+                        key.class_identifier.short_name(),
+                        key.name,
+                        key.descriptor.to_short_string()
+                    ))
+                    .color(Color32::WHITE),
+                )
+                .on_hover_text(key.to_string());
+                ui.end_row();
+            }
+        });
+}
+
+fn show_list(ui: &mut egui::Ui, title: &str, entries: &[String]) {
+    egui::CollapsingHeader::new(format!("{} ({})", title, entries.len()))
+        .id_salt(title)
+        .show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .id_salt(title)
+                .auto_shrink([false, true])
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for entry in entries {
+                        // call paths read easier as arrows than as folded stacks
+                        ui.label(RichText::new(entry.replace(';', " → ")).monospace());
+                    }
+                });
+        });
+}
@@ -0,0 +1,398 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use eframe::egui::{self, Align2, Color32, FontId, Rect, Sense, pos2, vec2};
+use shared::{
+    MethodEvent, ThreadInfo,
+    call_tree::{CallNode, CallTree},
+    folded_stacks::Weight,
+};
+
+use crate::{method_label, thread_label};
+
+const ROW_HEIGHT: f32 = 18.0;
+/// Frames narrower than this are not drawn, neither are their callees.
+const MIN_FRAME_WIDTH: f32 = 1.0;
+/// Frames narrower than this are drawn without a label.
+const MIN_LABEL_WIDTH: f32 = 24.0;
+
+enum FrameKind {
+    Root,
+    Thread(ThreadInfo),
+    /// Invocations of one method, represented by the entry event of the first.
+    Method {
+        entry: usize,
+    },
+}
+
+/// Invocations with the same stack merged into one frame.
+struct Frame {
+    kind: FrameKind,
+    self_calls: i64,
+    /// Exclusive time of the invocations that returned, in microseconds.
+    self_micros: i64,
+    /// Calls of this frame and all frames below it.
+    total_calls: i64,
+    total_micros: i64,
+    children: Vec<Frame>,
+}
+
+impl Frame {
+    fn new(kind: FrameKind) -> Self {
+        Self {
+            kind,
+            self_calls: 0,
+            self_micros: 0,
+            total_calls: 0,
+            total_micros: 0,
+            children: Vec::new(),
+        }
+    }
+
+    fn build(
+        call_tree: &CallTree,
+        method_events: &[MethodEvent],
+        merged: bool,
+        thread_filter: Option<u64>,
+    ) -> Self {
+        let mut root = Frame::new(FrameKind::Root);
+
+        for thread_call_tree in &call_tree.threads {
+            if thread_filter.is_some_and(|id| id != thread_call_tree.thread.id) {
+                continue;
+            }
+
+            let parent = if merged {
+                &mut root
+            } else {
+                root.children.push(Frame::new(FrameKind::Thread(
+                    thread_call_tree.thread.clone(),
+                )));
+                root.children.last_mut().unwrap()
+            };
+
+            for node in &thread_call_tree.roots {
+                parent.add(node, method_events);
+            }
+        }
+
+        root.sum_totals();
+        root
+    }
+
+    fn add(&mut self, node: &CallNode, method_events: &[MethodEvent]) {
+        let entry = &method_events[node.entry];
+        let index = match self.children.iter().position(|child| match child.kind {
+            FrameKind::Method { entry: other } => method_events[other].is_same_method(entry),
+            _ => false,
+        }) {
+            Some(index) => index,
+            None => {
+                self.children
+                    .push(Frame::new(FrameKind::Method { entry: node.entry }));
+                self.children.len() - 1
+            }
+        };
+
+        let frame = &mut self.children[index];
+        frame.self_calls += 1;
+        frame.self_micros += node.exclusive_time().unwrap_or(0);
+
+        for child in &node.children {
+            frame.add(child, method_events);
+        }
+    }
+
+    fn sum_totals(&mut self) {
+        self.total_calls = self.self_calls;
+        self.total_micros = self.self_micros;
+
+        for child in &mut self.children {
+            child.sum_totals();
+            self.total_calls += child.total_calls;
+            self.total_micros += child.total_micros;
+        }
+    }
+
+    fn value(&self, weight: Weight) -> i64 {
+        match weight {
+            Weight::Calls => self.total_calls,
+            Weight::Micros => self.total_micros,
+        }
+    }
+
+    fn depth(&self) -> usize {
+        1 + self.children.iter().map(Frame::depth).max().unwrap_or(0)
+    }
+
+    fn label(&self, method_events: &[MethodEvent]) -> String {
+        match &self.kind {
+            FrameKind::Root => "all".to_string(),
+            FrameKind::Thread(thread) => thread_label(thread),
+            FrameKind::Method { entry } => method_label(&method_events[*entry]),
+        }
+    }
+
+    fn tooltip(&self, method_events: &[MethodEvent]) -> String {
+        let heading = match &self.kind {
+            FrameKind::Root => "All threads".to_string(),
+            FrameKind::Thread(thread) => thread_label(thread),
+            FrameKind::Method { entry } => {
+                let entry = &method_events[*entry];
+                format!(
+                    "{}\n{}{}",
+                    entry.class_identifier,
+                    entry.name,
+                    entry.descriptor.to_descriptor()
+                )
+            }
+        };
+
+        format!(
+            "{}\n{} calls ({} here)\n{} total, {} self",
+            heading,
+            self.total_calls,
+            self.self_calls,
+            shared::format_duration(self.total_micros),
+            shared::format_duration(self.self_micros)
+        )
+    }
+
+    fn matches(&self, query: &str, method_events: &[MethodEvent]) -> bool {
+        match &self.kind {
+            FrameKind::Root | FrameKind::Thread(_) => false,
+            FrameKind::Method { entry } => {
+                shared::folded_stacks::frame_name(&method_events[*entry])
+                    .to_lowercase()
+                    .contains(query)
+            }
+        }
+    }
+
+    fn color(&self, method_events: &[MethodEvent]) -> Color32 {
+        match &self.kind {
+            FrameKind::Root | FrameKind::Thread(_) => Color32::from_gray(170),
+            FrameKind::Method { entry } => {
+                // methods of a class share their color
+                let mut hasher = DefaultHasher::new();
+                method_events[*entry]
+                    .class_identifier
+                    .to_string()
+                    .hash(&mut hasher);
+                let hash = hasher.finish();
+                Color32::from_rgb(
+                    205 + (hash % 50) as u8,
+                    80 + ((hash >> 8) % 130) as u8,
+                    30 + ((hash >> 16) % 50) as u8,
+                )
+            }
+        }
+    }
+}
+
+/// Icicle graph of the call stacks, callers on top of their callees.
+pub struct FlameGraph {
+    /// Merge the stacks of all threads instead of showing one subtree per thread.
+    merged: bool,
+    weight: Weight,
+    search: String,
+    /// Child indices from the root to the frame that fills the whole width.
+    zoom: Vec<usize>,
+    root: Frame,
+    /// Number of method events, `merged` and thread filter `root` was built with.
+    built_from: Option<(usize, bool, Option<u64>)>,
+}
+
+impl Default for FlameGraph {
+    fn default() -> Self {
+        Self {
+            merged: true,
+            weight: Weight::Micros,
+            search: String::new(),
+            zoom: Vec::new(),
+            root: Frame::new(FrameKind::Root),
+            built_from: None,
+        }
+    }
+}
+
+/// Where the frames of one pass are drawn and what the pointer is on.
+struct Canvas<'a> {
+    painter: egui::Painter,
+    rect: Rect,
+    pointer: Option<egui::Pos2>,
+    weight: Weight,
+    query: String,
+    method_events: &'a [MethodEvent],
+    /// Path and tooltip of the frame under the pointer.
+    hovered: Option<(Vec<usize>, String)>,
+}
+
+impl Canvas<'_> {
+    fn draw_frame(&mut self, frame: &Frame, path: &[usize], depth: usize, x: f32, width: f32) {
+        let frame_rect = Rect::from_min_size(
+            pos2(x, self.rect.top() + depth as f32 * ROW_HEIGHT),
+            vec2(width, ROW_HEIGHT),
+        );
+
+        let color = if self.query.is_empty() {
+            frame.color(self.method_events)
+        } else if frame.matches(&self.query, self.method_events) {
+            Color32::from_rgb(230, 80, 230)
+        } else {
+            Color32::from_gray(90)
+        };
+        self.painter
+            .rect_filled(frame_rect.shrink2(vec2(0.5, 0.5)), 2.0, color);
+
+        if width >= MIN_LABEL_WIDTH {
+            self.painter.with_clip_rect(frame_rect.shrink(2.0)).text(
+                frame_rect.left_center() + vec2(4.0, 0.0),
+                Align2::LEFT_CENTER,
+                frame.label(self.method_events),
+                FontId::proportional(12.0),
+                Color32::BLACK,
+            );
+        }
+
+        if self
+            .pointer
+            .is_some_and(|pointer| frame_rect.contains(pointer))
+        {
+            self.hovered = Some((path.to_vec(), frame.tooltip(self.method_events)));
+        }
+    }
+
+    fn draw_subtree(
+        &mut self,
+        frame: &Frame,
+        path: &mut Vec<usize>,
+        depth: usize,
+        x: f32,
+        width: f32,
+    ) {
+        self.draw_frame(frame, path, depth, x, width);
+
+        let total = frame.value(self.weight);
+        if total == 0 {
+            return;
+        }
+
+        let mut child_x = x;
+        for (index, child) in frame.children.iter().enumerate() {
+            let child_width = width * child.value(self.weight) as f32 / total as f32;
+            if child_width >= MIN_FRAME_WIDTH {
+                path.push(index);
+                self.draw_subtree(child, path, depth + 1, child_x, child_width);
+                path.pop();
+            }
+            child_x += child_width;
+        }
+    }
+}
+
+impl FlameGraph {
+    /// Drops the frames, they are rebuilt from the events of the next call to [`Self::show`].
+    pub fn clear(&mut self) {
+        self.root = Frame::new(FrameKind::Root);
+        self.zoom.clear();
+        self.built_from = None;
+    }
+
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        call_tree: &CallTree,
+        method_events: &[MethodEvent],
+        thread_filter: Option<u64>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.merged, true, "Merged");
+            ui.selectable_value(&mut self.merged, false, "Per thread");
+            ui.separator();
+            ui.selectable_value(&mut self.weight, Weight::Micros, "Time");
+            ui.selectable_value(&mut self.weight, Weight::Calls, "Calls");
+            ui.separator();
+            ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("Search"));
+            if ui
+                .add_enabled(!self.zoom.is_empty(), egui::Button::new("Reset zoom"))
+                .clicked()
+            {
+                self.zoom.clear();
+            }
+        });
+
+        let built_from = Some((method_events.len(), self.merged, thread_filter));
+        if self.built_from != built_from {
+            // paths only stay meaningful while events are appended
+            if self.built_from.map(|(_, merged, filter)| (merged, filter))
+                != Some((self.merged, thread_filter))
+            {
+                self.zoom.clear();
+            }
+            self.root = Frame::build(call_tree, method_events, self.merged, thread_filter);
+            self.built_from = built_from;
+        }
+
+        // frames of a previous build may be gone
+        let mut zoomed = &self.root;
+        for (depth, &index) in self.zoom.iter().enumerate() {
+            match zoomed.children.get(index) {
+                Some(child) => zoomed = child,
+                None => {
+                    self.zoom.truncate(depth);
+                    break;
+                }
+            }
+        }
+
+        if zoomed.value(self.weight) == 0 {
+            ui.label("No finished invocations");
+            return;
+        }
+
+        let depth = self.zoom.len() + zoomed.depth();
+        let (rect, response) = ui.allocate_exact_size(
+            vec2(ui.available_width(), depth as f32 * ROW_HEIGHT),
+            Sense::click(),
+        );
+
+        let mut canvas = Canvas {
+            painter: ui.painter_at(rect),
+            rect,
+            pointer: response.hover_pos(),
+            weight: self.weight,
+            query: self.search.to_lowercase(),
+            method_events,
+            hovered: None,
+        };
+
+        // callers of the zoomed frame span the whole width, clicking them zooms out
+        let mut ancestor = &self.root;
+        for (depth, &index) in self.zoom.iter().enumerate() {
+            canvas.draw_frame(
+                ancestor,
+                &self.zoom[..depth],
+                depth,
+                rect.left(),
+                rect.width(),
+            );
+            ancestor = &ancestor.children[index];
+        }
+
+        let mut path = self.zoom.clone();
+        canvas.draw_subtree(
+            ancestor,
+            &mut path,
+            self.zoom.len(),
+            rect.left(),
+            rect.width(),
+        );
+
+        if let Some((path, tooltip)) = canvas.hovered {
+            if response.clicked() {
+                self.zoom = path;
+            }
+            response.on_hover_text_at_pointer(tooltip);
+        }
+    }
+}
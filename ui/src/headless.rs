@@ -0,0 +1,446 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use shared::{
+    AgentMessage, ClassLoadEvent, Config, MethodEvent, allocation,
+    assertion::{self, Assertion, Report},
+    call_tree::CallTree,
+    chrome_trace, folded_stacks,
+    matcher::Matcher,
+    recording::{RecordingError, RecordingReader, RecordingWriter},
+    stats::{self, MethodStats},
+};
+
+use crate::{
+    Message,
+    process::{self, AGENT_TIMEOUT, OutputStream, ProcessExit},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    /// One JSON object per event and line.
+    JsonLines,
+    /// A single Chrome Trace Event document, written once all events have arrived.
+    ChromeTrace,
+    /// Collapsed stacks for flame graphs, written once all events have arrived.
+    FoldedStacks(folded_stacks::Weight),
+    /// Latency statistics per method, printed once all events have arrived.
+    Stats,
+}
+
+pub struct Options {
+    pub format: Format,
+    /// Events are written to stdout if this is `None`, Java's stdout then goes to stderr.
+    pub output: Option<PathBuf>,
+    pub record_path: Option<PathBuf>,
+}
+
+/// Writes events in the requested format, formats that describe the whole run and assertions
+/// collect them until [`EventWriter::finish`].
+///
+/// Assertion targets are traced even if the filters of the config leave them out. Their events
+/// are only checked, the output has the same events as without the assertions.
+struct EventWriter {
+    out: Box<dyn Write>,
+    format: Format,
+    /// Set once writing failed, later events are dropped.
+    failed: bool,
+    matcher: Matcher,
+    assertions: Vec<Assertion>,
+    class_load_events: Vec<ClassLoadEvent>,
+    method_events: Vec<MethodEvent>,
+}
+
+impl EventWriter {
+    fn create(options: &Options, config: &Config) -> Option<Self> {
+        let assertions = match Assertion::compile(&config.assertions) {
+            Ok(assertions) => assertions,
+            Err(err) => {
+                eprintln!("{}", err);
+                return None;
+            }
+        };
+
+        let matcher = match Matcher::new(config) {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                eprintln!("{}", err);
+                return None;
+            }
+        };
+
+        let out: Box<dyn Write> = match &options.output {
+            Some(path) => match File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(err) => {
+                    eprintln!("failed to create {}: {}", path.display(), err);
+                    return None;
+                }
+            },
+            None => Box::new(std::io::stdout().lock()),
+        };
+
+        Some(Self {
+            out,
+            format: options.format,
+            failed: false,
+            matcher,
+            assertions,
+            class_load_events: Vec::new(),
+            method_events: Vec::new(),
+        })
+    }
+
+    fn write(&mut self, msg: AgentMessage) {
+        let passes_filters = match &msg {
+            AgentMessage::ClassLoad(event) => passes_class_load_filters(&self.matcher, event),
+            AgentMessage::MethodEvent(event) => passes_method_filters(&self.matcher, event),
+            AgentMessage::Exception(_) | AgentMessage::Allocation(_) | AgentMessage::Unload => true,
+        };
+
+        // most likely a closed pipe, the run continues so Java's exit code is still reported
+        if passes_filters && !self.failed {
+            let result = match self.format {
+                Format::Text => match format_text(&msg) {
+                    Some(line) => writeln!(self.out, "{}", line),
+                    None => Ok(()),
+                },
+                Format::JsonLines => serde_json::to_writer(&mut self.out, &msg)
+                    .map_err(std::io::Error::from)
+                    .and_then(|_| writeln!(self.out)),
+                Format::ChromeTrace | Format::FoldedStacks(_) | Format::Stats => Ok(()),
+            };
+
+            if let Err(err) = result {
+                eprintln!("failed to write event: {}", err);
+                self.failed = true;
+            }
+        }
+
+        let collect = !self.assertions.is_empty()
+            || passes_filters
+                && matches!(
+                    self.format,
+                    Format::ChromeTrace | Format::FoldedStacks(_) | Format::Stats
+                );
+        if collect {
+            match msg {
+                AgentMessage::ClassLoad(event) => self.class_load_events.push(*event),
+                AgentMessage::MethodEvent(event) => self.method_events.push(*event),
+                AgentMessage::Exception(_) | AgentMessage::Allocation(_) | AgentMessage::Unload => {
+                }
+            }
+        }
+    }
+
+    /// Writes the formats that describe the whole run and checks the assertions, if any.
+    fn finish(mut self) -> Option<Report> {
+        let report = (!self.assertions.is_empty()).then(|| {
+            assertion::check(
+                &self.assertions,
+                &self.class_load_events,
+                &self.method_events,
+            )
+        });
+
+        // events of assertion targets alone were only collected for the check
+        if report.is_some() {
+            let matcher = &self.matcher;
+            self.class_load_events
+                .retain(|event| passes_class_load_filters(matcher, event));
+            self.method_events
+                .retain(|event| passes_method_filters(matcher, event));
+        }
+
+        let result = match self.format {
+            Format::ChromeTrace => {
+                chrome_trace::write(&mut self.out, &self.class_load_events, &self.method_events)
+                    .map_err(std::io::Error::from)
+            }
+            Format::FoldedStacks(weight) => {
+                folded_stacks::write(&mut self.out, &self.method_events, weight)
+            }
+            Format::Stats => {
+                let call_tree = CallTree::build(&self.method_events);
+                let stats = MethodStats::collect(&call_tree, &self.method_events);
+                stats::write_table(&mut self.out, &stats)
+            }
+            Format::Text | Format::JsonLines => Ok(()),
+        };
+
+        if let Err(err) = result {
+            eprintln!("failed to write {:?}: {}", self.format, err);
+        }
+
+        if let Err(err) = self.out.flush() {
+            eprintln!("failed to write events: {}", err);
+        }
+
+        report
+    }
+}
+
+/// Whether the filters of the config include the class load, as opposed to only an assertion.
+fn passes_class_load_filters(matcher: &Matcher, event: &ClassLoadEvent) -> bool {
+    matcher.filters_class_load(&event.class_identifier.to_string())
+}
+
+/// Whether the filters of the config include the method, as opposed to only an assertion.
+fn passes_method_filters(matcher: &Matcher, event: &MethodEvent) -> bool {
+    matcher.filters_method(
+        &event.name,
+        &event.class_identifier.to_string(),
+        &event.descriptor,
+    )
+}
+
+/// Runs the config at `path` once without the GUI and returns the exit code to exit with,
+/// which is Java's unless aida itself failed or an assertion of the config did not hold.
+/// Recordings are converted instead of run, their assertions are checked all the same.
+pub fn run(path: &str, options: Options) -> i32 {
+    // recordings are recognized by their header, everything else is read as a config
+    match RecordingReader::open(Path::new(path)) {
+        Ok(reader) => convert(reader, options),
+        Err(RecordingError::InvalidFormat(_)) => run_config(path, options),
+        Err(err) => {
+            eprintln!("failed to open {}: {}", path, err);
+            1
+        }
+    }
+}
+
+fn convert(mut reader: RecordingReader, options: Options) -> i32 {
+    if options.record_path.is_some() {
+        eprintln!("--record is ignored for recordings");
+    }
+
+    let Some(mut events) = EventWriter::create(&options, reader.config()) else {
+        return 1;
+    };
+
+    let mut exit_code = 0;
+    for msg in reader.messages() {
+        match msg {
+            Ok(msg) => events.write(msg),
+            Err(err) => {
+                eprintln!("failed to read recording: {}", err);
+                exit_code = 1;
+                break;
+            }
+        }
+    }
+
+    check_report(events.finish(), exit_code)
+}
+
+fn run_config(config_path: &str, options: Options) -> i32 {
+    let config = match shared::load_config(PathBuf::from(config_path)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("failed to load {}: {}", config_path, err);
+            return 2;
+        }
+    };
+
+    let Some(mut events) = EventWriter::create(&options, &config) else {
+        return 1;
+    };
+
+    let mut recorder = match &options.record_path {
+        Some(path) => match RecordingWriter::create(path, &config) {
+            Ok(recorder) => Some(recorder),
+            Err(err) => {
+                eprintln!("failed to create {}: {}", path.display(), err);
+                return 1;
+            }
+        },
+        None => None,
+    };
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let server_name = process::listen_for_agent(tx.clone());
+    let args = process::java_args(&server_name, config_path, &config.jar);
+    if let Err(err) = process::spawn(args, tx) {
+        eprintln!("failed to execute java: {}", err);
+        return 1;
+    }
+
+    let mut process_exit: Option<ProcessExit> = None;
+    let mut agent_finished = false;
+
+    while !(agent_finished && process_exit.is_some()) {
+        // the agent never connects if Java fails early, so don't wait forever after the exit
+        let msg = match process_exit {
+            Some(_) => match rx.recv_timeout(AGENT_TIMEOUT) {
+                Ok(msg) => msg,
+                Err(_) => break,
+            },
+            None => match rx.recv() {
+                Ok(msg) => msg,
+                Err(_) => break,
+            },
+        };
+
+        match msg {
+            Message::Agent(msg) => {
+                if let Some(writer) = &mut recorder
+                    && let Err(err) = writer.write(&msg)
+                {
+                    eprintln!("failed to record event: {}", err);
+                    recorder = None;
+                }
+
+                if matches!(msg, AgentMessage::Unload) {
+                    agent_finished = true;
+                }
+
+                events.write(msg);
+            }
+            Message::AgentDisconnected => {
+                eprintln!("agent disconnected without unloading");
+                agent_finished = true;
+            }
+            Message::Output(output_line) => match (output_line.stream, options.output.is_some()) {
+                (OutputStream::Stdout, true) => println!("{}", output_line.line),
+                _ => eprintln!("{}", output_line.line),
+            },
+            Message::Exited(exit) => process_exit = Some(exit),
+        }
+    }
+
+    let report = events.finish();
+
+    if let Some(recorder) = recorder
+        && let Err(err) = recorder.finish()
+    {
+        eprintln!("failed to finish recording: {}", err);
+    }
+
+    let exit_code = match process_exit {
+        Some(ProcessExit {
+            code: Some(code), ..
+        }) => code,
+        // same convention as shells
+        Some(ProcessExit {
+            signal: Some(signal),
+            ..
+        }) => 128 + signal,
+        _ => 1,
+    };
+
+    check_report(report, exit_code)
+}
+
+/// Prints the report to stderr and fails an otherwise successful run if an assertion failed.
+fn check_report(report: Option<Report>, exit_code: i32) -> i32 {
+    let Some(report) = report else {
+        return exit_code;
+    };
+
+    eprintln!("{}", report);
+    if exit_code == 0 && !report.passed() {
+        1
+    } else {
+        exit_code
+    }
+}
+
+fn format_text(msg: &AgentMessage) -> Option<String> {
+    let timestamp = |micros: i64| {
+        let timestamp: DateTime<Utc> = DateTime::from_timestamp_micros(micros).unwrap();
+        timestamp.to_rfc3339()
+    };
+
+    let line = match msg {
+        AgentMessage::Unload => return None,
+        AgentMessage::ClassLoad(event) => format!(
+            "{} [{}] load {}",
+            timestamp(event.timestamp),
+            event.thread.name,
+            event.class_identifier
+        ),
+        AgentMessage::MethodEvent(event) => {
+            let mut line = format!(
+                "{} [{}] {} {}.{}{}",
+                timestamp(event.timestamp),
+                event.thread.name,
+                match event.method_event_type {
+                    shared::MethodEventType::Entry => "->",
+                    shared::MethodEventType::Exit => "<-",
+                },
+                event.class_identifier,
+                event.name,
+                event.descriptor.to_short_string()
+            );
+
+            if !event.arguments.is_empty() {
+                let arguments: Vec<String> =
+                    event.arguments.iter().map(|a| a.to_string()).collect();
+                line.push_str(&format!(" {}", arguments.join(", ")));
+            }
+
+            if let Some(return_value) = &event.return_value {
+                line.push_str(&format!(" = {}", return_value));
+            }
+
+            if event.was_popped_by_exception {
+                line.push_str(" threw");
+            }
+
+            line
+        }
+        AgentMessage::Exception(event) => {
+            let mut line = format!(
+                "{} [{}] {} {}",
+                timestamp(event.timestamp),
+                event.thread.name,
+                match event.exception_event_type {
+                    shared::ExceptionEventType::Thrown => "throw",
+                    shared::ExceptionEventType::Caught => "catch",
+                },
+                event.class_identifier
+            );
+
+            if let Some(message) = &event.message {
+                line.push_str(&format!(" {:?}", message));
+            }
+
+            if let Some(throw_location) = &event.throw_location {
+                line.push_str(&format!(" at {}", throw_location));
+            }
+
+            match &event.catch_location {
+                Some(catch_location) => line.push_str(&format!(" caught in {}", catch_location)),
+                None => line.push_str(" uncaught"),
+            }
+
+            line
+        }
+        AgentMessage::Allocation(event) => {
+            let mut line = format!(
+                "{} [{}] {} {} {}",
+                timestamp(event.timestamp),
+                event.thread.name,
+                match event.allocation_event_type {
+                    shared::AllocationEventType::Sampled => "alloc",
+                    shared::AllocationEventType::VirtualMachine => "vm-alloc",
+                },
+                event.class_identifier,
+                allocation::format_bytes(event.size)
+            );
+
+            if let Some(site) = event.stack_trace.first() {
+                line.push_str(&format!(" at {}", site));
+            }
+
+            line
+        }
+    };
+
+    Some(line)
+}
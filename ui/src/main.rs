@@ -1,180 +1,795 @@
-use core::f32;
 use std::{
-    io::{BufRead, BufReader},
-    path::PathBuf,
-    process::{Command, Stdio},
-    sync::mpsc::{Receiver, Sender, TryRecvError},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, TryRecvError},
+    time::{Duration, Instant},
 };
 
+use allocations::AllocationView;
 use chrono::{DateTime, Utc};
+use diff::DiffView;
 use eframe::egui::{self, Color32, RichText};
-use ipc_channel::ipc::IpcOneShotServer;
+use flame_graph::FlameGraph;
+use process::{AGENT_TIMEOUT, OutputLine, OutputStream, ProcessExit, ProcessHandle};
+use replay::Replay;
+use shared::{
+    call_tree::CallTree,
+    folded_stacks::Weight,
+    recording::{RecordingError, RecordingReader, RecordingWriter},
+};
+use stats::StatsTable;
+use timeline::Timeline;
+
+mod allocations;
+mod call_tree;
+mod diff;
+mod flame_graph;
+mod headless;
+mod process;
+mod replay;
+mod stats;
+mod timeline;
+
+const USAGE: &str = "usage: ui <config.toml | recording> [--record <recording>]
+       ui --headless <config.toml | recording> [--format text|jsonl|chrome-trace|folded|stats] [--weight calls|micros]
+                [--output <file>] [--record <recording>]";
 
 fn main() {
-    let config_arg = std::env::args().nth(1).unwrap();
-    let config_path = PathBuf::from(config_arg.clone());
-    let config = shared::load_config(config_path);
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut record_path = None;
+    let mut headless = false;
+    let mut format = None;
+    let mut weight = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| usage_error(&format!("{} needs a value", arg)))
+        };
+
+        match arg.as_str() {
+            "--record" => record_path = Some(value()),
+            "--headless" => headless = true,
+            "--format" => format = Some(value()),
+            "--weight" => {
+                weight = match value().as_str() {
+                    "calls" => Some(Weight::Calls),
+                    "micros" => Some(Weight::Micros),
+                    other => usage_error(&format!("unknown weight '{}'", other)),
+                }
+            }
+            "--output" => output = Some(PathBuf::from(value())),
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option '{}'", flag)),
+            _ if path.is_some() => usage_error(&format!("unexpected argument '{}'", arg)),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage_error("missing config or recording"));
+    if !Path::new(&path).is_file() {
+        usage_error(&format!("no such file '{}'", path));
+    }
+
+    if headless {
+        let format = match format.as_deref() {
+            None | Some("text") => headless::Format::Text,
+            Some("jsonl") => headless::Format::JsonLines,
+            Some("chrome-trace") => headless::Format::ChromeTrace,
+            Some("folded") => headless::Format::FoldedStacks(weight.unwrap_or(Weight::Calls)),
+            Some("stats") => headless::Format::Stats,
+            Some(other) => usage_error(&format!("unknown format '{}'", other)),
+        };
+        if weight.is_some() && !matches!(format, headless::Format::FoldedStacks(_)) {
+            usage_error("--weight only applies to --format folded");
+        }
+
+        let options = headless::Options {
+            format,
+            output,
+            record_path: record_path.map(PathBuf::from),
+        };
+        std::process::exit(headless::run(&path, options));
+    }
+
+    if format.is_some() || weight.is_some() || output.is_some() {
+        usage_error("--format, --weight and --output need --headless");
+    }
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([320.0, 240.0]),
         ..Default::default()
     };
 
+    // recordings are recognized by their header, everything else is read as a config, which
+    // is loaded up front so that errors are reported like those in the arguments
+    let recording = match RecordingReader::open(Path::new(&path)) {
+        Err(RecordingError::InvalidFormat(_)) => match shared::load_config(PathBuf::from(&path)) {
+            Ok(config) => Err(config),
+            Err(err) => {
+                eprintln!("failed to load {}: {}", path, err);
+                std::process::exit(2);
+            }
+        },
+        recording => Ok(recording),
+    };
+
     eframe::run_native(
         "Confirm exit",
         options,
-        Box::new(|_cc| Ok(Box::new(App::new(config, config_arg)))),
+        Box::new(move |_cc| {
+            let app = match recording {
+                Ok(Ok(reader)) => {
+                    let mut app = App::new(
+                        reader.config().clone(),
+                        Source::Recording(PathBuf::from(&path)),
+                    );
+                    app.load_recording(reader, None);
+                    app
+                }
+                Ok(Err(err)) => {
+                    let mut app = App::new(
+                        shared::Config::default(),
+                        Source::Recording(PathBuf::from(&path)),
+                    );
+                    app.recording_error = Some(format!("failed to open {}: {}", path, err));
+                    app
+                }
+                Err(config) => {
+                    let mut app = App::new(config, Source::Config(path));
+                    app.record_path = record_path.unwrap_or_default();
+                    app.start();
+                    app
+                }
+            };
+
+            Ok(Box::new(app))
+        }),
     )
     .unwrap();
 }
 
+/// Prints `message` and the usage to stderr and exits with the conventional code for usage
+/// errors.
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    std::process::exit(2)
+}
+
+enum Message {
+    Agent(shared::AgentMessage),
+    /// The IPC connection closed without the agent sending [`shared::AgentMessage::Unload`].
+    AgentDisconnected,
+    Output(OutputLine),
+    Exited(ProcessExit),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SessionState {
+    Idle,
+    Running,
+    Stopping,
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AgentState {
+    Waiting,
+    Connected,
+    Unloaded,
+    Disconnected,
+}
+
+/// Where the events shown come from.
+enum Source {
+    /// Live sessions run with the config at this path.
+    Config(String),
+    Recording(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExportFormat {
+    ChromeTrace,
+    FoldedStacks(Weight),
+}
+
+/// How long a stopped JVM gets to shut down after SIGTERM before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
 struct App {
-    rx: Receiver<shared::AgentMessage>,
-    tx: Sender<shared::AgentMessage>,
+    /// Receives messages of the current session only, every session gets its own channel.
+    rx: Receiver<Message>,
     config: shared::Config,
-    config_arg: String,
-    stdout: Vec<String>,
-    stderr: Vec<String>,
+    source: Source,
+    session_state: SessionState,
+    agent_state: AgentState,
+    process: Option<ProcessHandle>,
+    /// Start a new session as soon as the current one has finished.
+    rerun_pending: bool,
+    /// Keep the events of previous sessions when starting a new one.
+    keep_events: bool,
+    /// Added to the thread ids of the current session. The agent numbers threads from 1 in
+    /// every session, so the ids would collide with those of kept events otherwise.
+    thread_id_offset: u64,
+    /// Stdout and stderr lines in the order they were read.
+    output: Vec<OutputLine>,
+    process_exit: Option<ProcessExit>,
+    /// When Java exited. The recording is finished once the agent is done, or after
+    /// [`AGENT_TIMEOUT`] if it never is.
+    exited_at: Option<Instant>,
+    spawn_error: Option<String>,
     class_load_events: Vec<shared::ClassLoadEvent>,
     method_events: Vec<shared::MethodEvent>,
-    running_command: bool,
-    done_command: bool,
+    exception_events: Vec<shared::ExceptionEvent>,
+    allocation_events: Vec<shared::AllocationEvent>,
+    thread_filter: Option<u64>,
+    group_by_thread: bool,
+    call_tree: CallTree,
+    /// Number of method events `call_tree` was built from.
+    call_tree_len: usize,
+    flame_graph: FlameGraph,
+    timeline: Timeline,
+    stats_table: StatsTable,
+    diff_view: DiffView,
+    allocation_view: AllocationView,
+    /// Method event a histogram link jumped to.
+    highlighted_event: Option<usize>,
+    /// Scroll the method events to `highlighted_event` with the next frame.
+    scroll_to_event: bool,
+    /// Path entered in "File → Open recording".
+    open_path: String,
+    /// Live sessions are recorded to this path unless it is empty.
+    record_path: String,
+    recorder: Option<RecordingWriter>,
+    recording_error: Option<String>,
+    replay: Option<Replay>,
+    /// Path entered in the "Export" menu.
+    export_path: String,
+    export_error: Option<String>,
+    /// Pace of replays relative to the recorded pace.
+    replay_speed: f64,
 }
 
 impl App {
-    fn new(config: shared::Config, config_arg: String) -> Self {
-        let (tx, rx) = std::sync::mpsc::channel();
+    fn new(config: shared::Config, source: Source) -> Self {
+        let (_, rx) = std::sync::mpsc::channel();
         Self {
             rx,
-            tx,
             config,
-            config_arg,
-            stdout: Vec::new(),
-            stderr: Vec::new(),
+            source,
+            session_state: SessionState::Idle,
+            agent_state: AgentState::Waiting,
+            process: None,
+            rerun_pending: false,
+            keep_events: false,
+            thread_id_offset: 0,
+            output: Vec::new(),
+            process_exit: None,
+            exited_at: None,
+            spawn_error: None,
             class_load_events: Vec::new(),
             method_events: Vec::new(),
-            running_command: false,
-            done_command: false,
+            exception_events: Vec::new(),
+            allocation_events: Vec::new(),
+            thread_filter: None,
+            group_by_thread: false,
+            call_tree: CallTree::default(),
+            call_tree_len: 0,
+            flame_graph: FlameGraph::default(),
+            timeline: Timeline::default(),
+            stats_table: StatsTable::default(),
+            diff_view: DiffView::default(),
+            allocation_view: AllocationView::default(),
+            highlighted_event: None,
+            scroll_to_event: false,
+            open_path: String::new(),
+            record_path: String::new(),
+            recorder: None,
+            recording_error: None,
+            replay: None,
+            export_path: String::new(),
+            export_error: None,
+            replay_speed: 1.0,
         }
     }
 
-    fn run_command(&mut self) {
-        let (server, server_name): (IpcOneShotServer<shared::AgentMessage>, String) =
-            IpcOneShotServer::new().unwrap();
+    /// Threads that sent method events, in order of their first event.
+    fn threads(&self) -> Vec<shared::ThreadInfo> {
+        let mut threads: Vec<shared::ThreadInfo> = Vec::new();
+        for method_event in &self.method_events {
+            if !threads.iter().any(|t| t.id == method_event.thread.id) {
+                threads.push(method_event.thread.clone());
+            }
+        }
+
+        threads
+    }
+
+    fn clear_events(&mut self) {
+        self.output.clear();
+        self.class_load_events.clear();
+        self.method_events.clear();
+        self.exception_events.clear();
+        self.allocation_events.clear();
+        self.call_tree = CallTree::default();
+        self.call_tree_len = 0;
+        self.flame_graph.clear();
+        self.timeline.clear();
+        self.stats_table.clear();
+        self.diff_view.clear();
+        self.allocation_view.clear();
+        self.highlighted_event = None;
+        self.thread_id_offset = 0;
+    }
 
-        let tx = self.tx.clone();
-        std::thread::spawn(move || {
-            let (rx, msg) = server.accept().unwrap();
+    /// Highest thread id of all events, 0 if there are none.
+    fn max_thread_id(&self) -> u64 {
+        let class_loads = self.class_load_events.iter().map(|e| e.thread.id);
+        let methods = self.method_events.iter().map(|e| e.thread.id);
+        let exceptions = self.exception_events.iter().map(|e| e.thread.id);
+        let allocations = self.allocation_events.iter().map(|e| e.thread.id);
 
-            if matches!(msg, shared::AgentMessage::Unload) {
-                tx.send(msg).unwrap();
-                return;
-            } else {
-                tx.send(msg).unwrap();
+        class_loads
+            .chain(methods)
+            .chain(exceptions)
+            .chain(allocations)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn start(&mut self) {
+        let Source::Config(config_path) = &self.source else {
+            return;
+        };
+        let config_path = config_path.clone();
+
+        if self.keep_events {
+            self.thread_id_offset = self.max_thread_id();
+        } else {
+            self.clear_events();
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.rx = rx;
+        self.process_exit = None;
+        self.exited_at = None;
+        self.spawn_error = None;
+        self.recording_error = None;
+        self.agent_state = AgentState::Waiting;
+
+        self.finish_recording();
+        if !self.record_path.is_empty() {
+            match RecordingWriter::create(Path::new(&self.record_path), &self.config) {
+                Ok(recorder) => self.recorder = Some(recorder),
+                Err(err) => self.recording_error = Some(err.to_string()),
             }
+        }
 
-            loop {
-                let msg = rx.recv().unwrap();
-                match msg {
-                    shared::AgentMessage::Unload => {
-                        tx.send(msg).unwrap();
-                        break;
-                    }
-                    msg => tx.send(msg).unwrap(),
+        let server_name = process::listen_for_agent(tx.clone());
+        let args = process::java_args(&server_name, &config_path, &self.config.jar);
+
+        match process::spawn(args, tx) {
+            Ok(process) => {
+                self.process = Some(process);
+                self.session_state = SessionState::Running;
+            }
+            Err(err) => {
+                self.spawn_error = Some(err.to_string());
+                self.session_state = SessionState::Finished;
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(process) = &self.process {
+            process.stop(STOP_TIMEOUT);
+            self.session_state = SessionState::Stopping;
+        }
+    }
+
+    fn rerun(&mut self) {
+        match self.session_state {
+            SessionState::Running | SessionState::Stopping => {
+                self.rerun_pending = true;
+                self.stop();
+            }
+            SessionState::Idle | SessionState::Finished => self.start(),
+        }
+    }
+
+    fn receive_messages(&mut self, ctx: &egui::Context) {
+        loop {
+            match self.rx.try_recv() {
+                Ok(msg) => {
+                    match msg {
+                        Message::Agent(msg) => {
+                            let unloaded = matches!(msg, shared::AgentMessage::Unload);
+                            self.record(&msg);
+                            self.handle_agent_msg(msg);
+
+                            if unloaded {
+                                self.finish_recording();
+                            }
+                        }
+                        Message::AgentDisconnected => {
+                            self.agent_state = AgentState::Disconnected;
+                            self.finish_recording();
+                        }
+                        Message::Output(line) => self.output.push(line),
+                        // agent messages arrive on another thread and often after the exit
+                        Message::Exited(process_exit) => {
+                            self.session_state = SessionState::Finished;
+                            self.process = None;
+                            self.process_exit = Some(process_exit);
+                            self.exited_at = Some(Instant::now());
+                        }
+                    };
+
+                    ctx.request_repaint();
                 }
+                Err(TryRecvError::Empty) => break,
+                // all senders of the session are gone, nothing more will arrive
+                Err(TryRecvError::Disconnected) => break,
             }
-        });
+        }
 
-        let agent_path = format!(
-            "-agentpath:target/debug/libaida.so={},{}",
-            server_name, self.config_arg
-        );
+        // the agent never connects if Java fails early
+        if self
+            .exited_at
+            .is_some_and(|exited_at| exited_at.elapsed() >= AGENT_TIMEOUT)
+        {
+            self.finish_recording();
+        }
 
-        let args = vec![agent_path.as_str(), "-jar", &self.config.jar];
+        if let Some(recorder) = &mut self.recorder
+            && let Err(err) = recorder.flush()
+        {
+            self.recording_error = Some(err.to_string());
+            self.recorder = None;
+        }
 
-        self.running_command = true;
+        if self.rerun_pending && self.session_state == SessionState::Finished {
+            self.rerun_pending = false;
+            self.start();
+        }
+    }
 
-        let mut cmd = Command::new("java")
-            .args(&args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .expect("failed to execute");
+    fn record(&mut self, msg: &shared::AgentMessage) {
+        if let Some(recorder) = &mut self.recorder
+            && let Err(err) = recorder.write(msg)
+        {
+            self.recording_error = Some(err.to_string());
+            self.recorder = None;
+        }
+    }
 
-        let stdout = cmd.stdout.take().expect("failed to capture stdout");
-        let stderr = cmd.stderr.take().expect("failed to capture stdout");
-        let stdout_reader = BufReader::new(stdout);
-        let stderr_reader = BufReader::new(stderr);
+    fn finish_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take()
+            && let Err(err) = recorder.finish()
+        {
+            self.recording_error = Some(err.to_string());
+        }
+    }
+
+    /// Shows a recording instead of the live sessions, any running session is stopped.
+    fn open_recording(&mut self, path: PathBuf) {
+        self.rerun_pending = false;
+        self.stop();
+
+        match RecordingReader::open(&path) {
+            Ok(reader) => {
+                self.config = reader.config().clone();
+                self.source = Source::Recording(path);
+                self.load_recording(reader, None);
+            }
+            Err(err) => {
+                self.recording_error = Some(format!("failed to open {}: {}", path.display(), err))
+            }
+        }
+    }
+
+    /// Replaces the events with the ones of `reader`, paced by `speed` or all at once.
+    fn load_recording(&mut self, reader: RecordingReader, speed: Option<f64>) {
+        // drop the messages of a session that is still shutting down
+        let (_, rx) = std::sync::mpsc::channel();
+        self.rx = rx;
+        self.process = None;
+        self.finish_recording();
+
+        self.clear_events();
+        self.process_exit = None;
+        self.exited_at = None;
+        self.spawn_error = None;
+        self.recording_error = None;
+        self.agent_state = AgentState::Waiting;
+        self.session_state = SessionState::Finished;
+        self.replay = Some(Replay::new(reader, speed));
+    }
+
+    fn replay_recording(&mut self, speed: Option<f64>) {
+        let Source::Recording(path) = &self.source else {
+            return;
+        };
+
+        match RecordingReader::open(path) {
+            Ok(reader) => self.load_recording(reader, speed),
+            Err(err) => self.recording_error = Some(err.to_string()),
+        }
+    }
+
+    fn advance_replay(&mut self, ctx: &egui::Context) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+
+        match replay.poll() {
+            Ok((messages, wait)) => {
+                for msg in messages {
+                    self.handle_agent_msg(msg);
+                }
+
+                if let Some(wait) = wait {
+                    ctx.request_repaint_after(wait);
+                }
+            }
+            Err(err) => {
+                self.recording_error = Some(err.to_string());
+                self.replay = None;
+            }
+        }
+    }
 
-        for line in stdout_reader.lines() {
-            self.stdout.push(line.unwrap());
+    fn handle_agent_msg(&mut self, msg: shared::AgentMessage) {
+        if self.agent_state == AgentState::Waiting {
+            self.agent_state = AgentState::Connected;
         }
 
-        for line in stderr_reader.lines() {
-            self.stderr.push(line.unwrap());
+        let offset = self.thread_id_offset;
+        match msg {
+            shared::AgentMessage::ClassLoad(mut event) => {
+                event.thread.id += offset;
+                self.class_load_events.push(*event);
+            }
+            shared::AgentMessage::MethodEvent(mut event) => {
+                event.thread.id += offset;
+                self.method_events.push(*event);
+            }
+            shared::AgentMessage::Exception(mut event) => {
+                event.thread.id += offset;
+                self.exception_events.push(*event);
+            }
+            shared::AgentMessage::Allocation(mut event) => {
+                event.thread.id += offset;
+                self.allocation_events.push(*event);
+            }
+            shared::AgentMessage::Unload => self.agent_state = AgentState::Unloaded,
+        };
+    }
+
+    fn export(&mut self, format: ExportFormat) {
+        let result = std::fs::File::create(&self.export_path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                let out = std::io::BufWriter::new(file);
+                match format {
+                    ExportFormat::ChromeTrace => shared::chrome_trace::write(
+                        out,
+                        &self.class_load_events,
+                        &self.method_events,
+                    )
+                    .map_err(|err| err.to_string()),
+                    ExportFormat::FoldedStacks(weight) => {
+                        shared::folded_stacks::write(out, &self.method_events, weight)
+                            .map_err(|err| err.to_string())
+                    }
+                }
+            });
+
+        self.export_error = result
+            .err()
+            .map(|err| format!("failed to export {}: {}", self.export_path, err));
+    }
+
+    /// Highlights a method event and scrolls the list to it.
+    fn jump_to_event(&mut self, index: usize) {
+        let thread_id = self.method_events[index].thread.id;
+        if self.thread_filter.is_some_and(|id| id != thread_id) {
+            self.thread_filter = None;
         }
 
-        cmd.wait().expect("failed to wait on command");
+        self.highlighted_event = Some(index);
+        self.scroll_to_event = true;
     }
 
-    fn receive_agent_msg(&mut self, ctx: &egui::Context) {
-        match self.rx.try_recv() {
-            Ok(msg) => {
-                match msg {
-                    shared::AgentMessage::ClassLoad(event) => self.class_load_events.push(event),
-                    shared::AgentMessage::MethodEvent(event) => self.method_events.push(event),
-                    shared::AgentMessage::Unload => {
-                        self.running_command = false;
-                        self.done_command = true;
+    fn show_menu(&mut self, ui: &mut egui::Ui) {
+        egui::MenuBar::new().ui(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                ui.label("Open recording");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.open_path);
+                    if ui.button("Open").clicked() && !self.open_path.is_empty() {
+                        self.open_recording(PathBuf::from(&self.open_path));
+                        ui.close();
                     }
-                };
+                });
+
+                ui.separator();
+
+                ui.label("Record live sessions to")
+                    .on_hover_text("Takes effect with the next run, leave empty to not record");
+                ui.text_edit_singleline(&mut self.record_path);
+            });
 
-                ctx.request_repaint();
+            ui.menu_button("Export", |ui| {
+                ui.label("Export events to");
+                ui.text_edit_singleline(&mut self.export_path);
+
+                let formats = [
+                    (
+                        ExportFormat::ChromeTrace,
+                        "Chrome trace",
+                        "Opens in ui.perfetto.dev and chrome://tracing",
+                    ),
+                    (
+                        ExportFormat::FoldedStacks(Weight::Calls),
+                        "Folded stacks by calls",
+                        "For inferno and flamegraph.pl",
+                    ),
+                    (
+                        ExportFormat::FoldedStacks(Weight::Micros),
+                        "Folded stacks by time",
+                        "For inferno and flamegraph.pl, weighted by exclusive time",
+                    ),
+                ];
+                for (format, label, hover_text) in formats {
+                    if ui
+                        .add_enabled(!self.export_path.is_empty(), egui::Button::new(label))
+                        .on_hover_text(hover_text)
+                        .clicked()
+                    {
+                        self.export(format);
+                        ui.close();
+                    }
+                }
+            });
+        });
+    }
+
+    fn show_replay_controls(&mut self, ui: &mut egui::Ui, path: &Path) {
+        ui.label(RichText::new(path.display().to_string()).color(Color32::GRAY));
+
+        if let Some(replay) = &mut self.replay
+            && !replay.is_finished()
+        {
+            let (delivered, total) = replay.progress();
+            ui.label(
+                RichText::new(format!("Replaying {}/{}", delivered, total)).color(Color32::YELLOW),
+            );
+            if ui.button("Skip to end").clicked() {
+                replay.skip_to_end();
             }
-            Err(err) => {
-                if matches!(err, TryRecvError::Disconnected) {
-                    panic!("{}", err);
+            return;
+        }
+
+        if ui.button("Replay").clicked() {
+            self.replay_recording(Some(self.replay_speed));
+        }
+        ui.add(
+            egui::DragValue::new(&mut self.replay_speed)
+                .range(0.01..=100.0)
+                .speed(0.05)
+                .suffix("×"),
+        )
+        .on_hover_text("Replay speed relative to the recording");
+    }
+
+    fn show_session_controls(&mut self, ui: &mut egui::Ui) {
+        if let Source::Recording(path) = &self.source {
+            let path = path.clone();
+            self.show_replay_controls(ui, &path);
+            return;
+        }
+
+        match self.session_state {
+            SessionState::Idle => {
+                if ui.button("Start").clicked() {
+                    self.start();
                 }
             }
+            SessionState::Running => {
+                ui.label(RichText::new("Running...").color(Color32::YELLOW));
+                if ui.button("Stop").clicked() {
+                    self.stop();
+                }
+                if ui.button("Re-run").clicked() {
+                    self.rerun();
+                }
+            }
+            SessionState::Stopping => {
+                ui.label(RichText::new("Stopping...").color(Color32::YELLOW));
+            }
+            SessionState::Finished => {
+                ui.label(RichText::new("Done").color(Color32::GREEN));
+                if ui.button("Re-run").clicked() {
+                    self.rerun();
+                }
+            }
+        }
+
+        ui.checkbox(&mut self.keep_events, "Keep previous events");
+
+        if self.agent_state == AgentState::Disconnected {
+            ui.label(RichText::new("Agent disconnected without unloading").color(Color32::RED));
         }
     }
 }
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if !self.done_command && !self.running_command {
-            self.run_command();
+        self.receive_messages(ctx);
+        self.advance_replay(ctx);
+
+        // messages arrive on background threads, keep polling while the process runs and
+        // until the recording is finished
+        if self.session_state != SessionState::Finished || self.recorder.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+
+        if self.call_tree_len != self.method_events.len() {
+            self.call_tree = CallTree::build(&self.method_events);
+            self.call_tree_len = self.method_events.len();
         }
 
-        self.receive_agent_msg(ctx);
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| self.show_menu(ui));
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.heading("Aida");
-                if self.running_command {
-                    ui.label(RichText::new("Running...").color(Color32::YELLOW));
+                self.show_session_controls(ui);
+
+                if let Some(process_exit) = &self.process_exit {
+                    let color = if process_exit.code == Some(0) {
+                        Color32::GREEN
+                    } else {
+                        Color32::RED
+                    };
+                    ui.label(RichText::new(process_exit.to_string()).color(color));
                 }
 
-                if self.done_command {
-                    ui.label(RichText::new("Done").color(Color32::GREEN));
+                if let Some(recording_error) = &self.recording_error {
+                    ui.label(RichText::new(recording_error).color(Color32::RED));
                 }
-            });
 
-            if !self.stdout.is_empty() {
-                egui::CollapsingHeader::new("Stdout")
-                    .default_open(true)
-                    .show(ui, |ui| {
-                        let mut text = self.stdout.join("\n");
-                        ui.add(egui::TextEdit::multiline(&mut text).desired_width(f32::INFINITY));
-                    });
-            }
+                if let Some(export_error) = &self.export_error {
+                    ui.label(RichText::new(export_error).color(Color32::RED));
+                }
+
+                if let Some(spawn_error) = &self.spawn_error {
+                    ui.label(
+                        RichText::new(format!("failed to execute java: {}", spawn_error))
+                            .color(Color32::RED),
+                    );
+                }
+            });
 
-            if !self.stderr.is_empty() {
-                egui::CollapsingHeader::new("Stderr")
+            if !self.output.is_empty() {
+                egui::CollapsingHeader::new("Output")
                     .default_open(true)
                     .show(ui, |ui| {
-                        let mut text = self.stderr.join("\n");
-                        ui.add(egui::TextEdit::multiline(&mut text).desired_width(f32::INFINITY));
+                        egui::ScrollArea::vertical()
+                            .id_salt("output")
+                            .auto_shrink([false, true])
+                            .max_height(200.0)
+                            .stick_to_bottom(true)
+                            .show(ui, |ui| {
+                                for output_line in &self.output {
+                                    let color = match output_line.stream {
+                                        OutputStream::Stdout => Color32::WHITE,
+                                        OutputStream::Stderr => Color32::RED,
+                                    };
+                                    ui.label(
+                                        RichText::new(&output_line.line).monospace().color(color),
+                                    );
+                                }
+                            });
                     });
             }
 
@@ -186,14 +801,35 @@ impl eframe::App for App {
                             .auto_shrink([false, true])
                             .show(ui, |ui| {
                                 for class_load_event in &self.class_load_events {
+                                    if self
+                                        .thread_filter
+                                        .is_some_and(|id| id != class_load_event.thread.id)
+                                    {
+                                        continue;
+                                    }
+
                                     let timestamp: DateTime<Utc> =
                                         DateTime::from_timestamp_micros(class_load_event.timestamp)
                                             .unwrap();
                                     ui.horizontal(|ui| {
                                         ui.label(timestamp.to_rfc3339());
                                         ui.label(
-                                            RichText::new(class_load_event.class_identifier.name())
-                                                .color(Color32::WHITE),
+                                            RichText::new(&class_load_event.thread.name)
+                                                .color(Color32::DARK_GRAY),
+                                        )
+                                        .on_hover_text(thread_label(&class_load_event.thread));
+                                        let type_parameters = class_load_event
+                                            .signature
+                                            .as_ref()
+                                            .map(|signature| signature.type_parameters_string(true))
+                                            .unwrap_or_default();
+                                        ui.label(
+                                            RichText::new(format!(
+                                                "{}{}",
+                                                class_load_event.class_identifier.short_name(),
+                                                type_parameters
+                                            ))
+                                            .color(Color32::WHITE),
                                         )
                                         .on_hover_text(
                                             class_load_event.class_identifier.to_string(),
@@ -204,55 +840,335 @@ impl eframe::App for App {
                     });
             }
 
+            let scroll_to_event = std::mem::take(&mut self.scroll_to_event);
             if !self.method_events.is_empty() {
                 egui::CollapsingHeader::new("Method events")
                     .default_open(true)
+                    .open(scroll_to_event.then_some(true))
                     .show(ui, |ui| {
+                        let threads = self.threads();
+
+                        ui.horizontal(|ui| {
+                            let selected_text = match self
+                                .thread_filter
+                                .and_then(|id| threads.iter().find(|t| t.id == id))
+                            {
+                                Some(thread) => thread_label(thread),
+                                None => "All threads".to_string(),
+                            };
+
+                            egui::ComboBox::from_id_salt("thread_filter")
+                                .selected_text(selected_text)
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(
+                                        &mut self.thread_filter,
+                                        None,
+                                        "All threads",
+                                    );
+                                    for thread in &threads {
+                                        ui.selectable_value(
+                                            &mut self.thread_filter,
+                                            Some(thread.id),
+                                            thread_label(thread),
+                                        );
+                                    }
+                                });
+
+                            ui.checkbox(&mut self.group_by_thread, "Group by thread");
+                        });
+
                         egui::ScrollArea::vertical()
                             .auto_shrink([false, true])
                             .show(ui, |ui| {
-                                for method_event in &self.method_events {
-                                    let timestamp: DateTime<Utc> =
-                                        DateTime::from_timestamp_micros(method_event.timestamp)
-                                            .unwrap();
-                                    ui.horizontal(|ui| {
-                                        ui.label(timestamp.to_rfc3339());
-
-                                        match method_event.method_event_type {
-                                            shared::MethodEventType::Entry => {
-                                                ui.label(RichText::new("->").color(Color32::GREEN))
-                                            }
-                                            shared::MethodEventType::Exit => {
-                                                ui.label(RichText::new("<-").color(Color32::RED))
-                                            }
-                                        };
+                                if self.group_by_thread {
+                                    for thread in &threads {
+                                        if self.thread_filter.is_some_and(|id| id != thread.id) {
+                                            continue;
+                                        }
 
-                                        ui.label(
-                                            RichText::new(method_event.class_identifier.name())
-                                                .color(Color32::GRAY),
-                                        )
-                                        .on_hover_text(method_event.class_identifier.to_string());
+                                        egui::CollapsingHeader::new(thread_label(thread))
+                                            .id_salt(("thread", thread.id))
+                                            .default_open(true)
+                                            .show(ui, |ui| {
+                                                for (index, method_event) in self
+                                                    .method_events
+                                                    .iter()
+                                                    .enumerate()
+                                                    .filter(|(_, e)| e.thread.id == thread.id)
+                                                {
+                                                    let row =
+                                                        method_event_row(ui, method_event, false);
+                                                    if self.highlighted_event == Some(index) {
+                                                        highlight_row(ui, &row, scroll_to_event);
+                                                    }
+                                                }
+                                            });
+                                    }
+                                } else {
+                                    for (index, method_event) in
+                                        self.method_events.iter().enumerate()
+                                    {
+                                        if self
+                                            .thread_filter
+                                            .is_some_and(|id| id != method_event.thread.id)
+                                        {
+                                            continue;
+                                        }
 
-                                        let name = if method_event.name == "<init>" {
-                                            method_event.class_identifier.name()
-                                        } else {
-                                            &method_event.name
-                                        };
+                                        let row = method_event_row(ui, method_event, true);
+                                        if self.highlighted_event == Some(index) {
+                                            highlight_row(ui, &row, scroll_to_event);
+                                        }
+                                    }
+                                }
+                            });
+                    });
+            }
 
-                                        ui.label(RichText::new(name).color(Color32::WHITE));
+            if !self.exception_events.is_empty() {
+                egui::CollapsingHeader::new("Exception events")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        egui::ScrollArea::vertical()
+                            .auto_shrink([false, true])
+                            .show(ui, |ui| {
+                                for exception_event in &self.exception_events {
+                                    if self
+                                        .thread_filter
+                                        .is_some_and(|id| id != exception_event.thread.id)
+                                    {
+                                        continue;
+                                    }
 
-                                        ui.label(
-                                            RichText::new(
-                                                method_event.descriptor.to_short_string(),
-                                            )
-                                            .color(Color32::WHITE),
-                                        )
-                                        .on_hover_text(method_event.descriptor.to_string());
-                                    });
+                                    exception_event_row(ui, exception_event);
                                 }
                             });
                     });
             }
+
+            if !self.call_tree.threads.is_empty() {
+                egui::CollapsingHeader::new("Call tree")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        egui::ScrollArea::vertical()
+                            .auto_shrink([false, true])
+                            .show(ui, |ui| {
+                                call_tree::show(
+                                    ui,
+                                    &self.call_tree,
+                                    &self.method_events,
+                                    self.thread_filter,
+                                );
+                            });
+                    });
+
+                egui::CollapsingHeader::new("Method statistics")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        egui::ScrollArea::vertical()
+                            .id_salt("method_stats")
+                            .auto_shrink([false, true])
+                            .max_height(300.0)
+                            .show(ui, |ui| {
+                                self.stats_table
+                                    .show(ui, &self.call_tree, &self.method_events);
+                            });
+
+                        if let Some(entry) =
+                            self.stats_table.show_histogram(ui, &self.method_events)
+                        {
+                            self.jump_to_event(entry);
+                        }
+                    });
+
+                egui::CollapsingHeader::new("Flame graph")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        self.flame_graph.show(
+                            ui,
+                            &self.call_tree,
+                            &self.method_events,
+                            self.thread_filter,
+                        );
+                    });
+            }
+
+            if !self.method_events.is_empty() || !self.class_load_events.is_empty() {
+                egui::CollapsingHeader::new("Timeline")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        self.timeline.show(
+                            ui,
+                            &self.call_tree,
+                            &self.method_events,
+                            &self.class_load_events,
+                            self.thread_filter,
+                        );
+                    });
+            }
+
+            if !self.allocation_events.is_empty() {
+                egui::CollapsingHeader::new("Allocations")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        self.allocation_view.show(
+                            ui,
+                            &self.allocation_events,
+                            self.config
+                                .allocations
+                                .as_ref()
+                                .map(|a| a.sampling_interval),
+                            self.thread_filter,
+                        );
+                    });
+            }
+
+            egui::CollapsingHeader::new("Diff").show(ui, |ui| {
+                self.diff_view
+                    .show(ui, &self.class_load_events, &self.method_events);
+            });
         });
     }
 }
+
+fn thread_label(thread: &shared::ThreadInfo) -> String {
+    format!("{} (#{})", thread.name, thread.id)
+}
+
+/// Name of the method, with constructors named after their class.
+fn method_name(method_event: &shared::MethodEvent) -> String {
+    if method_event.name == "<init>" {
+        method_event.class_identifier.class_name().to_string()
+    } else {
+        method_event.name.clone()
+    }
+}
+
+fn method_label(method_event: &shared::MethodEvent) -> String {
+    format!(
+        "{}.{}{}",
+        method_event.class_identifier.short_name(),
+        method_name(method_event),
+        method_event.descriptor.to_short_string()
+    )
+}
+
+fn exception_event_row(ui: &mut egui::Ui, exception_event: &shared::ExceptionEvent) {
+    let timestamp: DateTime<Utc> =
+        DateTime::from_timestamp_micros(exception_event.timestamp).unwrap();
+    ui.horizontal(|ui| {
+        ui.label(timestamp.to_rfc3339());
+        ui.label(RichText::new(&exception_event.thread.name).color(Color32::DARK_GRAY))
+            .on_hover_text(thread_label(&exception_event.thread));
+
+        match exception_event.exception_event_type {
+            shared::ExceptionEventType::Thrown => {
+                ui.label(RichText::new("throw").color(Color32::ORANGE))
+            }
+            shared::ExceptionEventType::Caught => {
+                ui.label(RichText::new("catch").color(Color32::GREEN))
+            }
+        };
+
+        ui.label(
+            RichText::new(exception_event.class_identifier.short_name()).color(Color32::WHITE),
+        )
+        .on_hover_text(exception_event.class_identifier.to_string());
+
+        if let Some(message) = &exception_event.message {
+            ui.label(RichText::new(format!("{:?}", message)).color(Color32::LIGHT_BLUE));
+        }
+
+        if let Some(throw_location) = &exception_event.throw_location {
+            ui.label(RichText::new(format!("at {}", throw_location)).color(Color32::GRAY))
+                .on_hover_text(throw_location.class_identifier.to_string());
+        }
+
+        match &exception_event.catch_location {
+            Some(catch_location) => {
+                ui.label(
+                    RichText::new(format!("caught in {}", catch_location)).color(Color32::GRAY),
+                )
+                .on_hover_text(catch_location.class_identifier.to_string());
+            }
+            None => {
+                ui.label(RichText::new("uncaught").color(Color32::RED));
+            }
+        }
+    });
+}
+
+/// Marks the row of the event a histogram link jumped to.
+fn highlight_row(ui: &egui::Ui, row: &egui::Response, scroll_to: bool) {
+    ui.painter().rect_stroke(
+        row.rect.expand(2.0),
+        2.0,
+        egui::Stroke::new(1.0, Color32::YELLOW),
+        egui::StrokeKind::Outside,
+    );
+
+    if scroll_to {
+        row.scroll_to_me(Some(egui::Align::Center));
+    }
+}
+
+fn method_event_row(
+    ui: &mut egui::Ui,
+    method_event: &shared::MethodEvent,
+    show_thread: bool,
+) -> egui::Response {
+    let timestamp: DateTime<Utc> = DateTime::from_timestamp_micros(method_event.timestamp).unwrap();
+    ui.horizontal(|ui| {
+        ui.label(timestamp.to_rfc3339());
+
+        if show_thread {
+            ui.label(RichText::new(&method_event.thread.name).color(Color32::DARK_GRAY))
+                .on_hover_text(thread_label(&method_event.thread));
+        }
+
+        match method_event.method_event_type {
+            shared::MethodEventType::Entry => ui.label(RichText::new("->").color(Color32::GREEN)),
+            shared::MethodEventType::Exit if method_event.was_popped_by_exception => ui
+                .label(RichText::new("<-").color(Color32::ORANGE))
+                .on_hover_text("popped by exception"),
+            shared::MethodEventType::Exit => ui.label(RichText::new("<-").color(Color32::RED)),
+        };
+
+        ui.label(RichText::new(method_event.class_identifier.short_name()).color(Color32::GRAY))
+            .on_hover_text(method_event.class_identifier.to_string());
+
+        match &method_event.signature {
+            Some(signature) => {
+                let name = method_name(method_event);
+                ui.label(RichText::new(signature.short_declaration(&name)).color(Color32::WHITE))
+                    .on_hover_text(signature.declaration(&name));
+            }
+            None => {
+                ui.label(RichText::new(method_name(method_event)).color(Color32::WHITE));
+
+                ui.label(
+                    RichText::new(method_event.descriptor.to_short_string()).color(Color32::WHITE),
+                )
+                .on_hover_text(method_event.descriptor.to_string());
+            }
+        }
+
+        if !method_event.arguments.is_empty() {
+            let arguments: Vec<String> = method_event
+                .arguments
+                .iter()
+                .map(|a| a.to_string())
+                .collect();
+            ui.label(RichText::new(arguments.join(", ")).color(Color32::LIGHT_BLUE));
+        }
+
+        if let Some(return_value) = &method_event.return_value {
+            ui.label(RichText::new(format!("= {}", return_value)).color(Color32::LIGHT_BLUE));
+        }
+
+        if method_event.was_popped_by_exception {
+            ui.label(RichText::new("threw").color(Color32::ORANGE));
+        }
+    })
+    .response
+}
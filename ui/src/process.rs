@@ -0,0 +1,207 @@
+use std::{
+    io::{BufRead, BufReader, Read},
+    os::unix::process::ExitStatusExt,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex, mpsc::Sender},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use ipc_channel::ipc::IpcOneShotServer;
+
+use crate::Message;
+
+/// How long to wait for the remaining agent messages once Java has exited.
+pub const AGENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessExit {
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+}
+
+impl From<ExitStatus> for ProcessExit {
+    fn from(status: ExitStatus) -> Self {
+        Self {
+            code: status.code(),
+            signal: status.signal(),
+        }
+    }
+}
+
+impl std::fmt::Display for ProcessExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.code, self.signal) {
+            (Some(code), _) => write!(f, "exited with code {}", code),
+            (None, Some(signal)) => write!(f, "killed by signal {}", signal),
+            (None, None) => write!(f, "exited"),
+        }
+    }
+}
+
+/// How often the process is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct ProcessHandle {
+    /// Only reaped while locked, so its pid can't be reused by another process while a lock
+    /// is held and the child has not exited.
+    child: Arc<Mutex<Child>>,
+}
+
+impl ProcessHandle {
+    /// Asks the process to terminate with SIGTERM and kills it with SIGKILL if it is still
+    /// alive after `timeout`.
+    pub fn stop(&self, timeout: Duration) {
+        {
+            let mut child = self.child.lock().unwrap();
+            if !matches!(child.try_wait(), Ok(None)) {
+                return;
+            }
+
+            unsafe {
+                libc::kill(child.id() as i32, libc::SIGTERM);
+            }
+        }
+
+        let child = self.child.clone();
+        std::thread::spawn(move || {
+            let deadline = Instant::now() + timeout;
+            while Instant::now() < deadline {
+                if !matches!(child.lock().unwrap().try_wait(), Ok(None)) {
+                    return;
+                }
+
+                std::thread::sleep(POLL_INTERVAL);
+            }
+
+            let mut child = child.lock().unwrap();
+            if matches!(child.try_wait(), Ok(None)) {
+                let _ = child.kill();
+            }
+        });
+    }
+}
+
+/// Accepts the connection of the agent on a background thread and forwards its messages.
+/// Returns the name of the server the agent has to connect to.
+pub fn listen_for_agent(tx: Sender<Message>) -> String {
+    let (server, server_name): (IpcOneShotServer<shared::AgentMessage>, String) =
+        IpcOneShotServer::new().unwrap();
+
+    std::thread::spawn(move || {
+        let (rx, mut msg) = match server.accept() {
+            Ok((rx, msg)) => (rx, Ok(msg)),
+            Err(_) => {
+                let _ = tx.send(Message::AgentDisconnected);
+                return;
+            }
+        };
+
+        loop {
+            match msg {
+                Ok(shared::AgentMessage::Unload) => {
+                    let _ = tx.send(Message::Agent(shared::AgentMessage::Unload));
+                    break;
+                }
+                Ok(msg) => {
+                    // the session was replaced, nobody is listening anymore
+                    if tx.send(Message::Agent(msg)).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => {
+                    let _ = tx.send(Message::AgentDisconnected);
+                    break;
+                }
+            }
+
+            msg = rx.recv();
+        }
+    });
+
+    server_name
+}
+
+/// Arguments to run `jar` with the agent connecting to `server_name`.
+pub fn java_args(server_name: &str, config_path: &str, jar: &str) -> Vec<String> {
+    let agent_path = format!(
+        "-agentpath:target/debug/libaida.so={},{}",
+        server_name, config_path
+    );
+
+    vec![agent_path, "-jar".to_string(), jar.to_string()]
+}
+
+/// Runs `java` with `args`. Output lines are forwarded from background threads as they are
+/// read, followed by a [`Message::Exited`] once the process is gone.
+pub fn spawn(args: Vec<String>, tx: Sender<Message>) -> std::io::Result<ProcessHandle> {
+    let mut child = Command::new("java")
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = child.stdout.take().expect("failed to capture stdout");
+    let stderr = child.stderr.take().expect("failed to capture stderr");
+    let stdout_reader = forward_lines(stdout, OutputStream::Stdout, tx.clone());
+    let stderr_reader = forward_lines(stderr, OutputStream::Stderr, tx.clone());
+
+    let child = Arc::new(Mutex::new(child));
+    let waited_child = child.clone();
+    std::thread::spawn(move || {
+        // polled instead of waiting, which would hold the lock until the process exits
+        let status = loop {
+            let status = waited_child
+                .lock()
+                .unwrap()
+                .try_wait()
+                .expect("failed to wait on command");
+            if let Some(status) = status {
+                break status;
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        };
+
+        // make sure all output is delivered before the exit
+        stdout_reader.join().unwrap();
+        stderr_reader.join().unwrap();
+
+        let _ = tx.send(Message::Exited(status.into()));
+    });
+
+    Ok(ProcessHandle { child })
+}
+
+fn forward_lines(
+    reader: impl Read + Send + 'static,
+    stream: OutputStream,
+    tx: Sender<Message>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if tx
+                .send(Message::Output(OutputLine { stream, line }))
+                .is_err()
+            {
+                break;
+            }
+        }
+    })
+}
@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+use shared::{
+    AgentMessage,
+    recording::{RecordingError, RecordingReader},
+};
+
+/// Feeds the events of a recording back into the UI, either all at once or paced by their
+/// timestamps.
+pub struct Replay {
+    reader: RecordingReader,
+    next: usize,
+    /// Multiplier for the recorded pace, `None` delivers everything at once.
+    speed: Option<f64>,
+    started: Instant,
+    first_timestamp: Option<i64>,
+    /// Read ahead message that is not due yet.
+    pending: Option<AgentMessage>,
+}
+
+impl Replay {
+    pub fn new(reader: RecordingReader, speed: Option<f64>) -> Self {
+        Self {
+            reader,
+            next: 0,
+            speed,
+            started: Instant::now(),
+            first_timestamp: None,
+            pending: None,
+        }
+    }
+
+    /// Returns the messages that are due by now and how long until the next one is.
+    pub fn poll(&mut self) -> Result<(Vec<AgentMessage>, Option<Duration>), RecordingError> {
+        let mut messages = Vec::new();
+
+        loop {
+            let message = match self.pending.take() {
+                Some(message) => message,
+                None => match self.reader.get(self.next) {
+                    Some(message) => {
+                        self.next += 1;
+                        message?
+                    }
+                    None => return Ok((messages, None)),
+                },
+            };
+
+            if let Some(wait) = self.time_until_due(&message) {
+                self.pending = Some(message);
+                return Ok((messages, Some(wait)));
+            }
+
+            messages.push(message);
+        }
+    }
+
+    /// Delivers the remaining messages with the next poll.
+    pub fn skip_to_end(&mut self) {
+        self.speed = None;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_none() && self.next >= self.reader.len()
+    }
+
+    /// Number of delivered messages and the total number of messages.
+    pub fn progress(&self) -> (usize, usize) {
+        let delivered = self.next - usize::from(self.pending.is_some());
+        (delivered, self.reader.len())
+    }
+
+    fn time_until_due(&mut self, message: &AgentMessage) -> Option<Duration> {
+        let speed = self.speed?;
+        let timestamp = message.timestamp()?;
+        let first_timestamp = *self.first_timestamp.get_or_insert(timestamp);
+
+        let due = Duration::from_micros((timestamp - first_timestamp).max(0) as u64)
+            .div_f64(speed.max(f64::EPSILON));
+        due.checked_sub(self.started.elapsed())
+            .filter(|wait| !wait.is_zero())
+    }
+}
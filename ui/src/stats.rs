@@ -0,0 +1,347 @@
+use chrono::{DateTime, Utc};
+use eframe::egui::{self, Align2, Color32, FontId, Rect, RichText, Sense, pos2, vec2};
+use shared::{
+    MethodEvent,
+    call_tree::CallTree,
+    format_duration,
+    stats::{MethodKey, MethodStats},
+};
+
+const BUCKETS_PER_DECADE: u32 = 4;
+const HISTOGRAM_HEIGHT: f32 = 120.0;
+const AXIS_HEIGHT: f32 = 14.0;
+/// Number of the slowest invocations listed as outliers.
+const OUTLIERS: usize = 5;
+/// Number of invocations listed for a clicked bucket.
+const MAX_BUCKET_INVOCATIONS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column {
+    Calls,
+    Total,
+    Mean,
+    Min,
+    Max,
+    P50,
+    P90,
+    P99,
+    Exclusive,
+    Method,
+}
+
+impl Column {
+    const ALL: [Column; 10] = [
+        Column::Calls,
+        Column::Total,
+        Column::Mean,
+        Column::Min,
+        Column::Max,
+        Column::P50,
+        Column::P90,
+        Column::P99,
+        Column::Exclusive,
+        Column::Method,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            Column::Calls => "calls",
+            Column::Total => "total",
+            Column::Mean => "mean",
+            Column::Min => "min",
+            Column::Max => "max",
+            Column::P50 => "p50",
+            Column::P90 => "p90",
+            Column::P99 => "p99",
+            Column::Exclusive => "excl",
+            Column::Method => "method",
+        }
+    }
+
+    fn duration(&self, method_stats: &MethodStats) -> Option<i64> {
+        match self {
+            Column::Total => Some(method_stats.total),
+            Column::Mean => Some(method_stats.mean),
+            Column::Min => Some(method_stats.min),
+            Column::Max => Some(method_stats.max),
+            Column::P50 => Some(method_stats.p50),
+            Column::P90 => Some(method_stats.p90),
+            Column::P99 => Some(method_stats.p99),
+            Column::Exclusive => Some(method_stats.exclusive),
+            Column::Calls | Column::Method => None,
+        }
+    }
+}
+
+/// Latency statistics per method, sortable by every column.
+pub struct StatsTable {
+    sort: Column,
+    descending: bool,
+    stats: Vec<MethodStats>,
+    /// Number of method events `stats` was built from.
+    built_from: Option<usize>,
+    /// Method whose histogram is shown.
+    selected: Option<MethodKey>,
+    /// Index of the clicked histogram bucket.
+    selected_bucket: Option<usize>,
+}
+
+impl Default for StatsTable {
+    fn default() -> Self {
+        Self {
+            sort: Column::Total,
+            descending: true,
+            stats: Vec::new(),
+            built_from: None,
+            selected: None,
+            selected_bucket: None,
+        }
+    }
+}
+
+impl StatsTable {
+    pub fn clear(&mut self) {
+        self.stats.clear();
+        self.built_from = None;
+        self.selected = None;
+        self.selected_bucket = None;
+    }
+
+    fn sort(&mut self) {
+        let column = self.sort;
+        self.stats.sort_by(|a, b| {
+            let ordering = match column {
+                Column::Calls => a.calls().cmp(&b.calls()),
+                Column::Method => a.key.to_string().cmp(&b.key.to_string()),
+                _ => column.duration(a).cmp(&column.duration(b)),
+            };
+
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, call_tree: &CallTree, method_events: &[MethodEvent]) {
+        if self.built_from != Some(method_events.len()) {
+            self.stats = MethodStats::collect(call_tree, method_events);
+            self.built_from = Some(method_events.len());
+            self.sort();
+        }
+
+        if self.stats.is_empty() {
+            ui.label("No finished invocations");
+            return;
+        }
+
+        egui::Grid::new("method_stats")
+            .striped(true)
+            .show(ui, |ui| {
+                for column in Column::ALL {
+                    let title = if self.sort == column {
+                        format!(
+                            "{} {}",
+                            column.title(),
+                            if self.descending { "⏷" } else { "⏶" }
+                        )
+                    } else {
+                        column.title().to_string()
+                    };
+
+                    if ui.selectable_label(self.sort == column, title).clicked() {
+                        if self.sort == column {
+                            self.descending = !self.descending;
+                        } else {
+                            self.sort = column;
+                            // largest numbers are the interesting ones, names read top down
+                            self.descending = column != Column::Method;
+                        }
+                        self.sort();
+                    }
+                }
+                ui.end_row();
+
+                for method_stats in &self.stats {
+                    for column in Column::ALL {
+                        match column {
+                            Column::Calls => {
+                                let calls = ui.label(method_stats.calls().to_string());
+                                if method_stats.running > 0 {
+                                    calls.on_hover_text(format!(
+                                        "{} more still running",
+                                        method_stats.running
+                                    ));
+                                }
+                            }
+                            Column::Method => {
+                                let key = &method_stats.key;
+                                let selected = self.selected.as_ref() == Some(key);
+                                if ui
+                                    .selectable_label(
+                                        selected,
+                                        RichText::new(short_method_name(key)).color(Color32::WHITE),
+                                    )
+                                    .on_hover_text(format!(
+                                        "{}\nClick to show the latency histogram",
+                                        key
+                                    ))
+                                    .clicked()
+                                {
+                                    self.selected = (!selected).then(|| key.clone());
+                                    self.selected_bucket = None;
+                                }
+                            }
+                            _ => {
+                                ui.label(format_duration(
+                                    column.duration(method_stats).unwrap_or_default(),
+                                ));
+                            }
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// Shows the latency histogram of the selected method, returns the entry event of an
+    /// invocation that was clicked.
+    pub fn show_histogram(
+        &mut self,
+        ui: &mut egui::Ui,
+        method_events: &[MethodEvent],
+    ) -> Option<usize> {
+        let key = self.selected.as_ref()?;
+        let Some(method_stats) = self.stats.iter().find(|s| &s.key == key) else {
+            ui.label(format!(
+                "{} has no finished invocations",
+                short_method_name(key)
+            ));
+            return None;
+        };
+
+        ui.label(
+            RichText::new(format!(
+                "{}: {} invocations",
+                short_method_name(key),
+                method_stats.calls()
+            ))
+            .color(Color32::WHITE),
+        );
+
+        let buckets = method_stats.histogram(BUCKETS_PER_DECADE);
+        let highest = buckets
+            .iter()
+            .map(|b| b.invocations.len())
+            .max()
+            .unwrap_or(1)
+            .max(1);
+
+        let (rect, response) = ui.allocate_exact_size(
+            vec2(ui.available_width(), HISTOGRAM_HEIGHT + AXIS_HEIGHT),
+            Sense::click(),
+        );
+        let painter = ui.painter_at(rect);
+        let bar_width = rect.width() / buckets.len() as f32;
+        let pointer = response.hover_pos();
+        let mut hovered = None;
+
+        for (index, bucket) in buckets.iter().enumerate() {
+            let x = rect.left() + index as f32 * bar_width;
+            let height = HISTOGRAM_HEIGHT * bucket.invocations.len() as f32 / highest as f32;
+            let column = Rect::from_x_y_ranges(x..=x + bar_width, rect.top()..=rect.bottom());
+            let bar = Rect::from_x_y_ranges(
+                x + 1.0..=x + bar_width - 1.0,
+                rect.top() + HISTOGRAM_HEIGHT - height..=rect.top() + HISTOGRAM_HEIGHT,
+            );
+
+            let color = if self.selected_bucket == Some(index) {
+                Color32::WHITE
+            } else if pointer.is_some_and(|pointer| column.contains(pointer)) {
+                Color32::LIGHT_BLUE
+            } else {
+                Color32::from_rgb(90, 140, 210)
+            };
+            painter.rect_filled(bar, 1.0, color);
+
+            // every other label, they would overlap otherwise
+            if index % 2 == 0 {
+                painter.text(
+                    pos2(x, rect.top() + HISTOGRAM_HEIGHT + 1.0),
+                    Align2::LEFT_TOP,
+                    format_duration(bucket.lower),
+                    FontId::proportional(10.0),
+                    Color32::GRAY,
+                );
+            }
+
+            if pointer.is_some_and(|pointer| column.contains(pointer)) {
+                hovered = Some(index);
+            }
+        }
+
+        if response.clicked() {
+            self.selected_bucket = hovered;
+        }
+        if let Some(index) = hovered {
+            let bucket = &buckets[index];
+            response.on_hover_text_at_pointer(format!(
+                "{} – {}: {} invocations",
+                format_duration(bucket.lower),
+                format_duration(bucket.upper),
+                bucket.invocations.len()
+            ));
+        }
+
+        let mut jump_to = None;
+        let (title, invocations) = match self.selected_bucket.and_then(|index| buckets.get(index)) {
+            Some(bucket) => (
+                format!(
+                    "{} – {}, slowest first",
+                    format_duration(bucket.lower),
+                    format_duration(bucket.upper)
+                ),
+                bucket.invocations.clone(),
+            ),
+            None => (
+                "Slowest invocations".to_string(),
+                method_stats.calls().saturating_sub(OUTLIERS)..method_stats.calls(),
+            ),
+        };
+
+        ui.label(RichText::new(title).color(Color32::GRAY));
+        for invocation in method_stats.invocations[invocations]
+            .iter()
+            .rev()
+            .take(MAX_BUCKET_INVOCATIONS)
+        {
+            let entry = &method_events[invocation.entry];
+            let timestamp: DateTime<Utc> =
+                DateTime::from_timestamp_micros(entry.timestamp).unwrap();
+            if ui
+                .link(format!(
+                    "{} at {} on {}",
+                    format_duration(invocation.duration),
+                    timestamp.to_rfc3339(),
+                    entry.thread.name
+                ))
+                .on_hover_text("Show in the method events")
+                .clicked()
+            {
+                jump_to = Some(invocation.entry);
+            }
+        }
+
+        jump_to
+    }
+}
+
+fn short_method_name(key: &MethodKey) -> String {
+    format!(
+        "{}.{}{}",
+        key.class_identifier.short_name(),
+        key.name,
+        key.descriptor.to_short_string()
+    )
+}
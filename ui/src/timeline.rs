@@ -0,0 +1,380 @@
+use eframe::egui::{self, Align2, Color32, FontId, Rect, RichText, Sense, Stroke, pos2, vec2};
+use shared::{
+    ClassLoadEvent, MethodEvent, ThreadInfo,
+    call_tree::{CallNode, CallTree},
+};
+
+use crate::{method_event_row, method_label, thread_label};
+
+const BAR_HEIGHT: f32 = 16.0;
+/// Height of the row with the class load markers at the top of every lane.
+const MARKER_HEIGHT: f32 = 8.0;
+const LANE_GAP: f32 = 6.0;
+const AXIS_HEIGHT: f32 = 16.0;
+/// Width of the thread names left of the lanes.
+const GUTTER_WIDTH: f32 = 140.0;
+/// Bars narrower than this are drawn without a label.
+const MIN_LABEL_WIDTH: f32 = 30.0;
+
+struct Lane<'a> {
+    thread: &'a ThreadInfo,
+    roots: &'a [CallNode],
+    depth: usize,
+}
+
+impl Lane<'_> {
+    fn height(&self) -> f32 {
+        MARKER_HEIGHT + self.depth as f32 * BAR_HEIGHT
+    }
+}
+
+/// Invocations as bars on one lane per thread, scrolled and zoomed along the time axis.
+#[derive(Default)]
+pub struct Timeline {
+    /// Visible range in microseconds since the epoch, `None` fits all events.
+    view: Option<(f64, f64)>,
+    /// Entry event of the invocation that was clicked.
+    selected: Option<usize>,
+}
+
+/// Maps timestamps to horizontal positions.
+struct Axis {
+    left: f32,
+    width: f32,
+    start: f64,
+    span: f64,
+}
+
+impl Axis {
+    fn x(&self, timestamp: f64) -> f32 {
+        self.left + ((timestamp - self.start) / self.span) as f32 * self.width
+    }
+
+    fn timestamp(&self, x: f32) -> f64 {
+        self.start + (x - self.left) as f64 / self.width as f64 * self.span
+    }
+}
+
+/// What the pointer is on.
+enum Hovered {
+    Bar(usize, String),
+    ClassLoad(String),
+}
+
+impl Timeline {
+    pub fn clear(&mut self) {
+        self.view = None;
+        self.selected = None;
+    }
+
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        call_tree: &CallTree,
+        method_events: &[MethodEvent],
+        class_load_events: &[ClassLoadEvent],
+        thread_filter: Option<u64>,
+    ) {
+        let timestamps = method_events
+            .iter()
+            .map(|e| e.timestamp)
+            .chain(class_load_events.iter().map(|e| e.timestamp));
+        let (Some(first), Some(last)) = (timestamps.clone().min(), timestamps.max()) else {
+            return;
+        };
+        // a single event still needs a span to be drawn in
+        let last = last.max(first + 1);
+
+        let lanes = lanes(call_tree, class_load_events, thread_filter);
+
+        let (start, end) = self.view.unwrap_or((first as f64, last as f64));
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.view.is_some(), egui::Button::new("Fit"))
+                .clicked()
+            {
+                self.view = None;
+            }
+            ui.label(
+                RichText::new(format!(
+                    "{} visible, drag to pan, ctrl + scroll or pinch to zoom",
+                    shared::format_duration((end - start) as i64)
+                ))
+                .color(Color32::GRAY),
+            );
+        });
+
+        let height = AXIS_HEIGHT
+            + lanes
+                .iter()
+                .map(|lane| lane.height() + LANE_GAP)
+                .sum::<f32>();
+        let (rect, response) =
+            ui.allocate_exact_size(vec2(ui.available_width(), height), Sense::click_and_drag());
+        let painter = ui.painter_at(rect);
+
+        let axis = Axis {
+            left: rect.left() + GUTTER_WIDTH,
+            width: (rect.width() - GUTTER_WIDTH).max(1.0),
+            start,
+            span: end - start,
+        };
+
+        draw_ticks(&painter, &axis, rect, first);
+
+        let pointer = response.hover_pos();
+        let mut hovered = None;
+        let mut top = rect.top() + AXIS_HEIGHT;
+        for lane in &lanes {
+            painter.text(
+                pos2(rect.left() + 2.0, top + MARKER_HEIGHT),
+                Align2::LEFT_TOP,
+                thread_label(lane.thread),
+                FontId::proportional(12.0),
+                Color32::GRAY,
+            );
+
+            let bars = painter.with_clip_rect(Rect::from_x_y_ranges(
+                axis.left..=rect.right(),
+                top..=top + lane.height(),
+            ));
+
+            for class_load_event in class_load_events
+                .iter()
+                .filter(|e| e.thread.id == lane.thread.id)
+            {
+                let x = axis.x(class_load_event.timestamp as f64);
+                let marker = Rect::from_center_size(
+                    pos2(x, top + MARKER_HEIGHT / 2.0),
+                    vec2(3.0, MARKER_HEIGHT),
+                );
+                bars.rect_filled(marker, 0.0, Color32::LIGHT_GREEN);
+
+                if pointer.is_some_and(|pointer| marker.expand2(vec2(2.0, 0.0)).contains(pointer)) {
+                    hovered = Some(Hovered::ClassLoad(format!(
+                        "load {}",
+                        class_load_event.class_identifier
+                    )));
+                }
+            }
+
+            let mut context = LaneContext {
+                painter: &bars,
+                axis: &axis,
+                method_events,
+                last,
+                pointer,
+                selected: self.selected,
+                hovered: &mut hovered,
+            };
+            for node in lane.roots {
+                context.draw_bar(node, top + MARKER_HEIGHT, 0);
+            }
+
+            top += lane.height() + LANE_GAP;
+        }
+
+        if let Some(pointer) = pointer {
+            // vertical scrolling is left to the surrounding scroll area
+            let scroll = ui.input(|i| i.smooth_scroll_delta);
+            let zoom = ui.input(|i| i.zoom_delta());
+            let pan = -(response.drag_delta().x + scroll.x) as f64 / axis.width as f64 * axis.span;
+
+            if zoom != 1.0 || pan != 0.0 {
+                let center = axis.timestamp(pointer.x);
+                let span = (axis.span / zoom as f64).max(1.0);
+                let start = center - (center - axis.start) * span / axis.span + pan;
+                self.view = Some((start, start + span));
+            }
+        }
+
+        if response.clicked() {
+            self.selected = match &hovered {
+                Some(Hovered::Bar(entry, _)) => Some(*entry),
+                _ => None,
+            };
+        }
+
+        match hovered {
+            Some(Hovered::Bar(_, tooltip)) | Some(Hovered::ClassLoad(tooltip)) => {
+                response.on_hover_text_at_pointer(tooltip);
+            }
+            None => {}
+        }
+
+        if let Some(node) = self.selected.and_then(|entry| find_node(call_tree, entry)) {
+            ui.separator();
+            method_event_row(ui, &method_events[node.entry], true);
+            match node.exit {
+                Some(exit) => method_event_row(ui, &method_events[exit], true),
+                None => ui.label(RichText::new("running").color(Color32::YELLOW)),
+            };
+            if let Some(inclusive) = node.inclusive_time() {
+                ui.label(shared::format_duration(inclusive));
+            }
+        }
+    }
+}
+
+/// Threads with invocations or class loads, in order of their first invocation.
+fn lanes<'a>(
+    call_tree: &'a CallTree,
+    class_load_events: &'a [ClassLoadEvent],
+    thread_filter: Option<u64>,
+) -> Vec<Lane<'a>> {
+    let mut lanes: Vec<Lane> = call_tree
+        .threads
+        .iter()
+        .map(|thread_call_tree| Lane {
+            thread: &thread_call_tree.thread,
+            roots: &thread_call_tree.roots,
+            depth: thread_call_tree.roots.iter().map(depth).max().unwrap_or(0),
+        })
+        .collect();
+
+    for class_load_event in class_load_events {
+        if !lanes
+            .iter()
+            .any(|l| l.thread.id == class_load_event.thread.id)
+        {
+            lanes.push(Lane {
+                thread: &class_load_event.thread,
+                roots: &[],
+                depth: 0,
+            });
+        }
+    }
+
+    lanes.retain(|lane| thread_filter.is_none_or(|id| id == lane.thread.id));
+    lanes
+}
+
+fn find_node(call_tree: &CallTree, entry: usize) -> Option<&CallNode> {
+    fn find(nodes: &[CallNode], entry: usize) -> Option<&CallNode> {
+        nodes.iter().find_map(|node| {
+            if node.entry == entry {
+                Some(node)
+            } else {
+                find(&node.children, entry)
+            }
+        })
+    }
+
+    call_tree
+        .threads
+        .iter()
+        .find_map(|thread_call_tree| find(&thread_call_tree.roots, entry))
+}
+
+fn depth(node: &CallNode) -> usize {
+    1 + node.children.iter().map(depth).max().unwrap_or(0)
+}
+
+/// Labels the axis with the time since the first event at evenly spaced ticks.
+fn draw_ticks(painter: &egui::Painter, axis: &Axis, rect: Rect, first: i64) {
+    // 1, 2 or 5 times a power of ten, about one tick per 100 points
+    let target = axis.span * 100.0 / axis.width as f64;
+    let magnitude = 10f64.powf(target.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= target)
+        .unwrap_or(10.0 * magnitude)
+        .max(1.0);
+
+    let mut tick = ((axis.start - first as f64) / step).ceil() * step + first as f64;
+    while tick <= axis.start + axis.span {
+        let x = axis.x(tick);
+        painter.line_segment(
+            [pos2(x, rect.top() + AXIS_HEIGHT), pos2(x, rect.bottom())],
+            Stroke::new(1.0, Color32::from_gray(50)),
+        );
+        painter.text(
+            pos2(x + 2.0, rect.top()),
+            Align2::LEFT_TOP,
+            shared::format_duration((tick - first as f64).round() as i64),
+            FontId::proportional(11.0),
+            Color32::GRAY,
+        );
+        tick += step;
+    }
+}
+
+struct LaneContext<'a> {
+    painter: &'a egui::Painter,
+    axis: &'a Axis,
+    method_events: &'a [MethodEvent],
+    /// Where bars of invocations that have not returned end.
+    last: i64,
+    pointer: Option<egui::Pos2>,
+    selected: Option<usize>,
+    hovered: &'a mut Option<Hovered>,
+}
+
+impl LaneContext<'_> {
+    fn draw_bar(&mut self, node: &CallNode, top: f32, depth: usize) {
+        let x0 = self.axis.x(node.start as f64);
+        let x1 = self
+            .axis
+            .x(node.end.unwrap_or(self.last) as f64)
+            .max(x0 + 1.0);
+        let clip = self.painter.clip_rect();
+        if x1 < clip.left() || x0 > clip.right() {
+            return;
+        }
+
+        let bar = Rect::from_x_y_ranges(
+            x0..=x1,
+            top + depth as f32 * BAR_HEIGHT..=top + (depth + 1) as f32 * BAR_HEIGHT - 1.0,
+        );
+
+        let threw = node
+            .exit
+            .is_some_and(|exit| self.method_events[exit].was_popped_by_exception);
+        let color = if node.end.is_none() {
+            Color32::from_gray(120)
+        } else if threw {
+            Color32::ORANGE
+        } else {
+            Color32::from_rgb(90, 140, 210)
+        };
+        self.painter.rect_filled(bar, 1.0, color);
+
+        if self.selected == Some(node.entry) {
+            self.painter.rect_stroke(
+                bar,
+                1.0,
+                Stroke::new(2.0, Color32::WHITE),
+                egui::StrokeKind::Inside,
+            );
+        }
+
+        let entry = &self.method_events[node.entry];
+        let visible = bar.intersect(clip);
+        if visible.width() >= MIN_LABEL_WIDTH {
+            self.painter.with_clip_rect(visible.shrink(1.0)).text(
+                visible.left_center() + vec2(3.0, 0.0),
+                Align2::LEFT_CENTER,
+                method_label(entry),
+                FontId::proportional(11.0),
+                Color32::BLACK,
+            );
+        }
+
+        if self.pointer.is_some_and(|pointer| bar.contains(pointer)) {
+            let duration = match node.inclusive_time() {
+                Some(inclusive) => shared::format_duration(inclusive),
+                None => "running".to_string(),
+            };
+            *self.hovered = Some(Hovered::Bar(
+                node.entry,
+                format!("{}\n{}", method_label(entry), duration),
+            ));
+        }
+
+        for child in &node.children {
+            self.draw_bar(child, top, depth + 1);
+        }
+    }
+}